@@ -30,27 +30,27 @@ fn main() -> pebble::Result<()> {
     println!("Created heroes table");
 
     // Insert heroes with their primary attributes
-    let invoker = Hero {
+    let mut invoker = Hero {
         id: 1,
         name: "Invoker".into(),
         attribute: "Intelligence".into(),
     };
 
-    let juggernaut = Hero {
+    let mut juggernaut = Hero {
         id: 2,
         name: "Juggernaut".into(),
         attribute: "Agility".into(),
     };
 
-    let axe = Hero {
+    let mut axe = Hero {
         id: 3,
         name: "Axe".into(),
         attribute: "Strength".into(),
     };
 
-    db.insert(&invoker)?;
-    db.insert(&juggernaut)?;
-    db.insert(&axe)?;
+    db.insert(&mut invoker)?;
+    db.insert(&mut juggernaut)?;
+    db.insert(&mut axe)?;
     println!("Inserted 3 heroes");
 
     // Select all heroes
@@ -66,12 +66,12 @@ fn main() -> pebble::Result<()> {
     }
 
     // Update hero
-    let updated_invoker = Hero {
+    let mut updated_invoker = Hero {
         id: 1,
         name: "Invoker".into(),
         attribute: "Intelligence".into(),
     };
-    db.update(&updated_invoker)?;
+    db.update(&mut updated_invoker)?;
     println!("\nUpdated Invoker");
 
     // Verify update
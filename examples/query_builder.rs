@@ -1,4 +1,4 @@
-use pebble::{Database, Model, QueryBuilder};
+use pebble::{Database, Model};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -28,7 +28,7 @@ fn main() -> pebble::Result<()> {
     println!("Database ready");
 
     // Insert items with accurate costs based on Dota 2
-    let items = vec![
+    let mut items = vec![
         // Basic items
         Item {
             id: 1,
@@ -101,14 +101,14 @@ fn main() -> pebble::Result<()> {
         },
     ];
 
-    for item in &items {
+    for item in &mut items {
         db.insert(item)?;
     }
     println!("Inserted {} items\n", items.len());
 
     // Example 1: Filter by category - Basic items
     println!("Basic starting items:");
-    let basic_items = QueryBuilder::new::<Item>(&db.conn)
+    let basic_items = db.query::<Item>()
         .where_eq("category", "Basic")
         .fetch::<Item>()?;
     
@@ -118,7 +118,7 @@ fn main() -> pebble::Result<()> {
 
     // Example 2: Order by cost (most expensive first)
     println!("\nMost expensive items:");
-    let by_price = QueryBuilder::new::<Item>(&db.conn)
+    let by_price = db.query::<Item>()
         .order_by("cost", false)
         .limit(5)
         .fetch::<Item>()?;
@@ -129,7 +129,7 @@ fn main() -> pebble::Result<()> {
 
     // Example 3: Affordable early game items under 1000 gold
     println!("\nAffordable early game items (under 1000 gold):");
-    let affordable = QueryBuilder::new::<Item>(&db.conn)
+    let affordable = db.query::<Item>()
         .where_lt("cost", "1000")
         .order_by("cost", true)
         .fetch::<Item>()?;
@@ -140,7 +140,7 @@ fn main() -> pebble::Result<()> {
 
     // Example 4: Find specific item
     println!("\nFind Blink Dagger:");
-    let result = QueryBuilder::new::<Item>(&db.conn)
+    let result = db.query::<Item>()
         .where_eq("name", "Blink Dagger")
         .fetch_one::<Item>()?;
     
@@ -150,7 +150,7 @@ fn main() -> pebble::Result<()> {
 
     // Example 5: High tier items over 4000 gold
     println!("\nHigh tier items (over 4000 gold):");
-    let expensive = QueryBuilder::new::<Item>(&db.conn)
+    let expensive = db.query::<Item>()
         .where_gt("cost", "4000")
         .order_by("cost", true)
         .fetch::<Item>()?;
@@ -161,7 +161,7 @@ fn main() -> pebble::Result<()> {
 
     // Example 6: Support items
     println!("\nSupport items:");
-    let support_items = QueryBuilder::new::<Item>(&db.conn)
+    let support_items = db.query::<Item>()
         .where_eq("category", "Support")
         .fetch::<Item>()?;
     
@@ -175,7 +175,7 @@ fn main() -> pebble::Result<()> {
 
     // Example 7: Weapon category items
     println!("\nWeapon items:");
-    let weapons = QueryBuilder::new::<Item>(&db.conn)
+    let weapons = db.query::<Item>()
         .where_eq("category", "Weapons")
         .order_by("cost", false)
         .fetch::<Item>()?;
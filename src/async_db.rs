@@ -0,0 +1,142 @@
+use crate::db::Database;
+use crate::model::Model;
+use crate::query::QueryBuilder;
+use rusqlite::Result as SqliteResult;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::sync::mpsc;
+use std::thread;
+use tokio::sync::oneshot;
+
+type Job = Box<dyn FnOnce(&Database) + Send>;
+
+/// Async handle to a `Database` running on its own worker thread
+///
+/// Every call is sent to the worker and awaited, so async code never blocks on
+/// SQLite. Clones share the same worker, which runs calls one at a time in the
+/// order they were made, and the thread exits once the last clone is dropped.
+/// The futures only need a tokio-compatible executor to be polled on.
+#[derive(Clone)]
+pub struct AsyncDatabase {
+    jobs: mpsc::Sender<Job>,
+}
+
+impl AsyncDatabase {
+    /// Move a configured database onto a new worker thread
+    pub fn new(db: Database) -> Self {
+        let (jobs, queue) = mpsc::channel::<Job>();
+        spawn_worker(move || serve(db, queue)).expect("failed to spawn database worker thread");
+        AsyncDatabase { jobs }
+    }
+
+    /// Connect to or create a SQLite database file
+    pub async fn connect<P: AsRef<Path>>(path: P) -> SqliteResult<Self> {
+        let path = path.as_ref().to_path_buf();
+        Self::open(move || Database::connect(path)).await
+    }
+
+    /// Connect to an in-memory database (useful for testing)
+    pub async fn connect_in_memory() -> SqliteResult<Self> {
+        Self::open(Database::connect_in_memory).await
+    }
+
+    /// Open the database on the worker thread itself, so connecting does not block either
+    async fn open<F>(open: F) -> SqliteResult<Self>
+    where F: FnOnce() -> SqliteResult<Database> + Send + 'static {
+        let (jobs, queue) = mpsc::channel::<Job>();
+        let (ready, opened) = oneshot::channel();
+        spawn_worker(move || match open() {
+            Ok(db) => {
+                let _ = ready.send(Ok(()));
+                serve(db, queue);
+            }
+            Err(e) => {
+                let _ = ready.send(Err(e));
+            }
+        })
+        .map_err(|_| worker_stopped())?;
+
+        opened.await.map_err(|_| worker_stopped())??;
+        Ok(AsyncDatabase { jobs })
+    }
+
+    /// Run a closure against the database on the worker thread
+    pub async fn call<R, F>(&self, f: F) -> SqliteResult<R>
+    where
+        R: Send + 'static,
+        F: FnOnce(&Database) -> SqliteResult<R> + Send + 'static,
+    {
+        let (reply, result) = oneshot::channel();
+        let job: Job = Box::new(move |db| {
+            let _ = reply.send(f(db));
+        });
+        self.jobs.send(job).map_err(|_| worker_stopped())?;
+        result.await.map_err(|_| worker_stopped())?
+    }
+
+    /// Create a table for the given model
+    pub async fn create_table<T: Model>(&self) -> SqliteResult<()> {
+        self.call(|db| db.create_table::<T>()).await
+    }
+
+    /// Insert a model instance into the database
+    /// Returns the model, as changed by its insert hooks, along with the new row id
+    pub async fn insert<T: Model + Send + 'static>(&self, mut model: T) -> SqliteResult<(T, i64)> {
+        self.call(move |db| db.insert(&mut model).map(|id| (model, id))).await
+    }
+
+    /// Select all rows from a model's table
+    pub async fn select_all<T: Model + Send + 'static>(&self) -> SqliteResult<Vec<T>> {
+        self.call(|db| db.select_all::<T>()).await
+    }
+
+    /// Find a single row by primary key
+    pub async fn find_by_id<T: Model + Send + 'static>(&self, id: i64) -> SqliteResult<Option<T>> {
+        self.call(move |db| db.find_by_id::<T>(id)).await
+    }
+
+    /// Update a model instance in the database
+    /// Returns the model, as changed by its update hooks and with any new version, along
+    /// with the number of rows changed
+    pub async fn update<T: Model + Send + 'static>(&self, mut model: T) -> SqliteResult<(T, usize)> {
+        self.call(move |db| db.update(&mut model).map(|changed| (model, changed))).await
+    }
+
+    /// Delete a row by primary key
+    pub async fn delete<T: Model>(&self, id: i64) -> SqliteResult<usize> {
+        self.call(move |db| db.delete::<T>(id)).await
+    }
+
+    /// Build a query with `build` and fetch its results
+    pub async fn fetch<T, F>(&self, build: F) -> SqliteResult<Vec<T>>
+    where
+        T: Model + Send + 'static,
+        F: FnOnce(QueryBuilder<'_>) -> QueryBuilder<'_> + Send + 'static,
+    {
+        self.call(move |db| build(db.query::<T>()).fetch::<T>()).await
+    }
+}
+
+impl From<Database> for AsyncDatabase {
+    fn from(db: Database) -> Self {
+        AsyncDatabase::new(db)
+    }
+}
+
+fn spawn_worker(run: impl FnOnce() + Send + 'static) -> std::io::Result<()> {
+    thread::Builder::new().name("pebble-db".to_string()).spawn(run).map(|_| ())
+}
+
+fn serve(db: Database, queue: mpsc::Receiver<Job>) {
+    for job in queue {
+        // A panicking call drops its reply channel; keep serving the others
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| job(&db)));
+    }
+}
+
+fn worker_stopped() -> rusqlite::Error {
+    rusqlite::Error::SqliteFailure(
+        rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_MISUSE),
+        Some("database worker thread stopped before replying".to_string()),
+    )
+}
@@ -0,0 +1,252 @@
+use crate::datetime::timestamp_value;
+use crate::logging::Tracer;
+use crate::model::{ColumnType, Model};
+use crate::statement_cache::{prepare, StatementCache};
+use crate::util::quote_ident;
+use rusqlite::types::Value as SqlValue;
+use rusqlite::{params_from_iter, Connection, Result as SqliteResult};
+use std::time::SystemTime;
+
+/// What happened to a row
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Insert,
+    Update,
+    Delete,
+}
+
+/// One recorded change to an audited row
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistoryEntry {
+    /// Position in the history table; later changes have larger ids
+    pub id: i64,
+    /// Primary key of the changed row
+    pub row_id: i64,
+    pub action: Action,
+    /// Column values before the change, `None` for inserts
+    pub old_values: Option<serde_json::Value>,
+    /// Column values after the change, `None` for permanent deletes
+    pub new_values: Option<serde_json::Value>,
+    /// ISO-8601 time of the change, from the database clock
+    pub changed_at: String,
+    /// The actor set with `Database::set_actor` when the change was made
+    pub actor: Option<String>,
+}
+
+/// Runs audit statements on a writer's connection, through its statement cache and query logger
+pub(crate) struct Auditor<'a> {
+    pub(crate) conn: &'a Connection,
+    pub(crate) statements: Option<&'a StatementCache>,
+    pub(crate) tracer: &'a Tracer,
+    /// Who is making changes and when, captured when a write starts
+    pub(crate) actor: Option<String>,
+    pub(crate) now: SystemTime,
+}
+
+/// Which rows a write touches
+pub(crate) enum Target {
+    /// The row just inserted, found by `last_insert_rowid`
+    Inserted,
+    /// Rows matching a WHERE clause (including the `WHERE` keyword) and its parameters,
+    /// each tagged with the field it is compared against for logging
+    Where(String, Vec<(Option<String>, SqlValue)>),
+}
+
+impl Action {
+    fn as_str(self) -> &'static str {
+        match self {
+            Action::Insert => "insert",
+            Action::Update => "update",
+            Action::Delete => "delete",
+        }
+    }
+
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "insert" => Some(Action::Insert),
+            "update" => Some(Action::Update),
+            "delete" => Some(Action::Delete),
+            _ => None,
+        }
+    }
+}
+
+/// Statements creating the history table for an audited model
+pub(crate) fn history_ddl(history: &str) -> [String; 2] {
+    [
+        format!(
+            "CREATE TABLE IF NOT EXISTS {} (\"id\" INTEGER PRIMARY KEY, \"row_id\" INTEGER NOT NULL, \
+             \"action\" TEXT NOT NULL CHECK (\"action\" IN ('insert', 'update', 'delete')), \
+             \"old_values\" TEXT, \"new_values\" TEXT, \"changed_at\" TEXT NOT NULL, \"actor\" TEXT)",
+            quote_ident(history)
+        ),
+        format!(
+            "CREATE INDEX IF NOT EXISTS {} ON {} (\"row_id\")",
+            quote_ident(&format!("{}_row_id", history)),
+            quote_ident(history)
+        ),
+    ]
+}
+
+impl Auditor<'_> {
+    /// Run `write` inside a savepoint, recording a history entry for every row it changed
+    ///
+    /// Rows are snapshotted as JSON objects before and after the write, so changes made by
+    /// bulk updates are captured as well as single-row CRUD calls.
+    pub(crate) fn record<T: Model>(
+        &self,
+        history: &str,
+        action: Action,
+        target: Target,
+        write: impl FnOnce() -> SqliteResult<usize>,
+    ) -> SqliteResult<usize> {
+        self.batch("SAVEPOINT pebble_audit")?;
+        let result = (|| {
+            let before = match &target {
+                Target::Inserted => Vec::new(),
+                Target::Where(filter, params) => self.snapshot::<T>(filter, params)?,
+            };
+
+            let changed = write()?;
+
+            let filter = format!(" WHERE {} = ?", quote_ident(T::primary_key()));
+            let by_id = |id: i64| vec![(Some(T::primary_key().to_string()), SqlValue::Integer(id))];
+            let changes: Vec<(i64, Option<String>, Option<String>)> = match target {
+                Target::Inserted => {
+                    let after = self.snapshot::<T>(&filter, &by_id(self.conn.last_insert_rowid()))?;
+                    after.into_iter().map(|(id, new)| (id, None, Some(new))).collect()
+                }
+                Target::Where(..) => {
+                    let mut changes = Vec::new();
+                    for (id, old) in before {
+                        let new = self.snapshot::<T>(&filter, &by_id(id))?.pop().map(|(_, new)| new);
+                        if new.as_ref() != Some(&old) {
+                            changes.push((id, Some(old), new));
+                        }
+                    }
+                    changes
+                }
+            };
+
+            let sql = format!(
+                "INSERT INTO {} (\"row_id\", \"action\", \"old_values\", \"new_values\", \"changed_at\", \"actor\") \
+                 VALUES (?, ?, ?, ?, ?, ?)",
+                quote_ident(history)
+            );
+            let changed_at = timestamp_value(&self.now, ColumnType::Text)?;
+            let actor = self.actor.clone().map_or(SqlValue::Null, SqlValue::Text);
+            let json = |snapshot: &Option<String>| snapshot.clone().map_or(SqlValue::Null, SqlValue::Text);
+            for (id, old, new) in changes {
+                let started = self.tracer.start();
+                let values = [
+                    SqlValue::Integer(id),
+                    SqlValue::Text(action.as_str().to_string()),
+                    json(&old),
+                    json(&new),
+                    changed_at.clone(),
+                    actor.clone(),
+                ];
+                let written = prepare(self.conn, self.statements, &sql)?.execute(params_from_iter(values.iter()))?;
+                if started.is_some() {
+                    // Snapshots are logged with redacted fields masked inside the JSON
+                    let redacted = |snapshot: &Option<String>| {
+                        snapshot.as_deref().map_or(SqlValue::Null, |json| SqlValue::Text(self.tracer.redact_object(json)))
+                    };
+                    let logged = [values[0].clone(), values[1].clone(), redacted(&old), redacted(&new), changed_at.clone(), actor.clone()];
+                    let fields = ["row_id", "action", "old_values", "new_values", "changed_at", "actor"];
+                    self.tracer.finish(started, &sql, fields.into_iter().map(Some).zip(&logged), written);
+                }
+            }
+
+            Ok(changed)
+        })();
+
+        match result {
+            Ok(changed) => {
+                self.batch("RELEASE pebble_audit")?;
+                Ok(changed)
+            }
+            Err(e) => {
+                let _ = self.batch("ROLLBACK TO pebble_audit");
+                let _ = self.batch("RELEASE pebble_audit");
+                Err(e)
+            }
+        }
+    }
+
+    /// Read the recorded history of one row, oldest first
+    pub(crate) fn history(&self, history: &str, row_id: i64) -> SqliteResult<Vec<HistoryEntry>> {
+        let sql = format!(
+            "SELECT \"id\", \"row_id\", \"action\", \"old_values\", \"new_values\", \"changed_at\", \"actor\" \
+             FROM {} WHERE \"row_id\" = ? ORDER BY \"id\"",
+            quote_ident(history)
+        );
+        let json = |idx: usize, text: Option<String>| -> SqliteResult<Option<serde_json::Value>> {
+            text.map(|text| serde_json::from_str(&text))
+                .transpose()
+                .map_err(|e| rusqlite::Error::FromSqlConversionFailure(idx, rusqlite::types::Type::Text, Box::new(e)))
+        };
+
+        let started = self.tracer.start();
+        let mut stmt = prepare(self.conn, self.statements, &sql)?;
+        let rows = stmt.query_map([row_id], |row| {
+            let action: String = row.get(2)?;
+            Ok(HistoryEntry {
+                id: row.get(0)?,
+                row_id: row.get(1)?,
+                action: Action::parse(&action).ok_or_else(|| rusqlite::Error::InvalidColumnType(
+                    2,
+                    "action".to_string(),
+                    rusqlite::types::Type::Text,
+                ))?,
+                old_values: json(3, row.get(3)?)?,
+                new_values: json(4, row.get(4)?)?,
+                changed_at: row.get(5)?,
+                actor: row.get(6)?,
+            })
+        })?;
+        let entries = rows.collect::<SqliteResult<Vec<_>>>()?;
+        self.tracer.finish(started, &sql, [(Some("row_id"), &SqlValue::Integer(row_id))], entries.len());
+        Ok(entries)
+    }
+
+    /// Primary keys and JSON objects of the matching rows' columns
+    /// BLOBs are stored as hex since JSON cannot hold them
+    fn snapshot<T: Model>(&self, filter: &str, params: &[(Option<String>, SqlValue)]) -> SqliteResult<Vec<(i64, String)>> {
+        let pairs: Vec<String> = T::fields()
+            .iter()
+            .map(|field| {
+                let column = quote_ident(field);
+                format!(
+                    "'{}', CASE WHEN typeof({1}) = 'blob' THEN hex({1}) ELSE {1} END",
+                    field.replace('\'', "''"),
+                    column
+                )
+            })
+            .collect();
+        let sql = format!(
+            "SELECT {}, json_object({}) FROM {}{}",
+            quote_ident(T::primary_key()),
+            pairs.join(", "),
+            quote_ident(T::table_name()),
+            filter
+        );
+
+        let started = self.tracer.start();
+        let mut stmt = prepare(self.conn, self.statements, &sql)?;
+        let rows = stmt.query_map(params_from_iter(params.iter().map(|(_, value)| value)), |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })?;
+        let snapshots = rows.collect::<SqliteResult<Vec<_>>>()?;
+        self.tracer.finish(started, &sql, params.iter().map(|(field, value)| (field.as_deref(), value)), snapshots.len());
+        Ok(snapshots)
+    }
+
+    /// Run a savepoint statement, which is neither cached nor bound
+    fn batch(&self, sql: &str) -> SqliteResult<()> {
+        let started = self.tracer.start();
+        self.conn.execute_batch(sql)?;
+        self.tracer.finish(started, sql, [], 0);
+        Ok(())
+    }
+}
@@ -0,0 +1,453 @@
+//! Date and time column support
+//!
+//! Timestamps are stored either as canonical ISO-8601 text
+//! (`2024-05-01T12:30:00.000000000Z`, dates as `2024-05-01`) or as unix
+//! epoch seconds. The ISO-8601 form is fixed-width UTC, so SQLite's text
+//! comparison orders it chronologically; it covers the years 0000 to 9999,
+//! and values outside them are rejected when written. The unix form should be
+//! paired with `ColumnType::Integer` so comparisons are numeric.
+//!
+//! The representation is chosen per field with serde's `with` attribute:
+//!
+//! ```rust
+//! use pebble::{ColumnType, Model};
+//! use serde::{Deserialize, Serialize};
+//! use std::time::SystemTime;
+//!
+//! #[derive(Serialize, Deserialize)]
+//! struct Event {
+//!     id: i32,
+//!     #[serde(with = "pebble::datetime::iso8601")]
+//!     starts_at: SystemTime,
+//!     #[serde(with = "pebble::datetime::unix::option")]
+//!     cancelled_at: Option<SystemTime>,
+//! }
+//!
+//! impl Model for Event {
+//!     fn table_name() -> &'static str {
+//!         "events"
+//!     }
+//!
+//!     fn fields() -> &'static [&'static str] {
+//!         &["id", "starts_at", "cancelled_at"]
+//!     }
+//!
+//!     fn column_type(field: &str) -> ColumnType {
+//!         match field {
+//!             "cancelled_at" => ColumnType::Integer,
+//!             _ => ColumnType::Text,
+//!         }
+//!     }
+//! }
+//! ```
+//!
+//! `std::time::SystemTime` is always supported. The `chrono` feature adds
+//! `DateTime<Utc>`, `NaiveDateTime` and `NaiveDate`; the `time` feature adds
+//! `OffsetDateTime`, `PrimitiveDateTime` and `Date`. Values without an offset
+//! are treated as UTC.
+
+use crate::model::ColumnType;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const NANOS_PER_SEC: u32 = 1_000_000_000;
+const SECS_PER_DAY: i64 = 86_400;
+
+/// A date or date-time value that can be stored in a Pebble column
+pub trait Timestamp: Sized {
+    /// Whether the value only carries a calendar date
+    const DATE_ONLY: bool = false;
+
+    /// Returns seconds since the unix epoch and the sub-second nanoseconds
+    fn to_unix_parts(&self) -> (i64, u32);
+
+    /// Builds a value from seconds since the unix epoch and sub-second nanoseconds
+    fn from_unix_parts(secs: i64, nanos: u32) -> Option<Self>;
+}
+
+/// Source of the current time for automatically managed timestamps
+///
+/// Closures returning `SystemTime` implement this, which makes it easy to
+/// freeze time in tests with `Database::with_clock`.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> SystemTime;
+}
+
+/// The system wall clock (used by default)
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+impl<F: Fn() -> SystemTime + Send + Sync> Clock for F {
+    fn now(&self) -> SystemTime {
+        self()
+    }
+}
+
+/// Value written to a managed timestamp column or compared by `QueryBuilder::where_time_*`
+/// INTEGER columns get unix seconds, anything else canonical ISO-8601 text
+pub(crate) fn timestamp_value<T: Timestamp>(value: &T, column_type: ColumnType) -> rusqlite::Result<rusqlite::types::Value> {
+    match column_type {
+        ColumnType::Integer => Ok(rusqlite::types::Value::Integer(unix::timestamp(value))),
+        _ => iso8601::format(value).map(rusqlite::types::Value::Text).ok_or_else(|| {
+            rusqlite::Error::ToSqlConversionFailure(iso8601::OUT_OF_RANGE.into())
+        }),
+    }
+}
+
+/// Store timestamps as canonical ISO-8601 text
+pub mod iso8601 {
+    use super::Timestamp;
+    use serde::{de, ser, Deserialize, Deserializer, Serializer};
+
+    pub(crate) const OUT_OF_RANGE: &str = "timestamp outside the years 0000 to 9999 cannot be stored as ISO-8601";
+
+    /// Format a value in the canonical ISO-8601 form used for storage, or `None`
+    /// when its year is outside 0000 to 9999
+    ///
+    /// `QueryBuilder::where_time_*` formats values this way for non-INTEGER columns.
+    pub fn format<T: Timestamp>(value: &T) -> Option<String> {
+        let (secs, nanos) = value.to_unix_parts();
+        if T::DATE_ONLY {
+            super::format_date(secs)
+        } else {
+            super::format_datetime(secs, nanos)
+        }
+    }
+
+    /// Parse an ISO-8601 date or date-time
+    pub fn parse<T: Timestamp>(s: &str) -> Option<T> {
+        let (secs, nanos) = super::parse_datetime(s)?;
+        T::from_unix_parts(secs, nanos)
+    }
+
+    pub fn serialize<T: Timestamp, S: Serializer>(value: &T, serializer: S) -> Result<S::Ok, S::Error> {
+        let formatted = format(value).ok_or_else(|| ser::Error::custom(OUT_OF_RANGE))?;
+        serializer.serialize_str(&formatted)
+    }
+
+    pub fn deserialize<'de, T: Timestamp, D: Deserializer<'de>>(deserializer: D) -> Result<T, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        parse(&s).ok_or_else(|| de::Error::custom(format!("invalid ISO-8601 timestamp: {}", s)))
+    }
+
+    /// ISO-8601 storage for `Option` fields (`None` is stored as NULL)
+    pub mod option {
+        use crate::datetime::Timestamp;
+        use serde::{de, ser, Deserialize, Deserializer, Serializer};
+
+        pub fn serialize<T: Timestamp, S: Serializer>(value: &Option<T>, serializer: S) -> Result<S::Ok, S::Error> {
+            match value {
+                Some(v) => {
+                    let formatted = super::format(v).ok_or_else(|| ser::Error::custom(super::OUT_OF_RANGE))?;
+                    serializer.serialize_some(&formatted)
+                }
+                None => serializer.serialize_none(),
+            }
+        }
+
+        pub fn deserialize<'de, T: Timestamp, D: Deserializer<'de>>(deserializer: D) -> Result<Option<T>, D::Error> {
+            match Option::<String>::deserialize(deserializer)? {
+                Some(s) => super::parse(&s)
+                    .map(Some)
+                    .ok_or_else(|| de::Error::custom(format!("invalid ISO-8601 timestamp: {}", s))),
+                None => Ok(None),
+            }
+        }
+    }
+}
+
+/// Store timestamps as unix epoch seconds (sub-second precision is dropped)
+pub mod unix {
+    use super::Timestamp;
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    /// Seconds since the unix epoch
+    ///
+    /// `QueryBuilder::where_time_*` compares INTEGER columns this way.
+    pub fn timestamp<T: Timestamp>(value: &T) -> i64 {
+        value.to_unix_parts().0
+    }
+
+    pub fn serialize<T: Timestamp, S: Serializer>(value: &T, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_i64(timestamp(value))
+    }
+
+    pub fn deserialize<'de, T: Timestamp, D: Deserializer<'de>>(deserializer: D) -> Result<T, D::Error> {
+        let secs = i64::deserialize(deserializer)?;
+        T::from_unix_parts(secs, 0)
+            .ok_or_else(|| de::Error::custom(format!("unix timestamp out of range: {}", secs)))
+    }
+
+    /// Unix epoch storage for `Option` fields (`None` is stored as NULL)
+    pub mod option {
+        use crate::datetime::Timestamp;
+        use serde::{de, Deserialize, Deserializer, Serializer};
+
+        pub fn serialize<T: Timestamp, S: Serializer>(value: &Option<T>, serializer: S) -> Result<S::Ok, S::Error> {
+            match value {
+                Some(v) => serializer.serialize_some(&super::timestamp(v)),
+                None => serializer.serialize_none(),
+            }
+        }
+
+        pub fn deserialize<'de, T: Timestamp, D: Deserializer<'de>>(deserializer: D) -> Result<Option<T>, D::Error> {
+            match Option::<i64>::deserialize(deserializer)? {
+                Some(secs) => T::from_unix_parts(secs, 0)
+                    .map(Some)
+                    .ok_or_else(|| de::Error::custom(format!("unix timestamp out of range: {}", secs))),
+                None => Ok(None),
+            }
+        }
+    }
+}
+
+impl Timestamp for SystemTime {
+    fn to_unix_parts(&self) -> (i64, u32) {
+        match self.duration_since(UNIX_EPOCH) {
+            Ok(d) => (d.as_secs() as i64, d.subsec_nanos()),
+            Err(e) => {
+                let d = e.duration();
+                if d.subsec_nanos() == 0 {
+                    (-(d.as_secs() as i64), 0)
+                } else {
+                    (-(d.as_secs() as i64) - 1, NANOS_PER_SEC - d.subsec_nanos())
+                }
+            }
+        }
+    }
+
+    fn from_unix_parts(secs: i64, nanos: u32) -> Option<Self> {
+        if secs >= 0 {
+            UNIX_EPOCH.checked_add(Duration::new(secs as u64, nanos))
+        } else {
+            UNIX_EPOCH
+                .checked_sub(Duration::from_secs(secs.unsigned_abs()))?
+                .checked_add(Duration::from_nanos(nanos as u64))
+        }
+    }
+}
+
+#[cfg(feature = "chrono")]
+mod chrono_impls {
+    use super::Timestamp;
+    use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+
+    impl Timestamp for DateTime<Utc> {
+        fn to_unix_parts(&self) -> (i64, u32) {
+            (self.timestamp(), self.timestamp_subsec_nanos())
+        }
+
+        fn from_unix_parts(secs: i64, nanos: u32) -> Option<Self> {
+            DateTime::from_timestamp(secs, nanos)
+        }
+    }
+
+    impl Timestamp for NaiveDateTime {
+        fn to_unix_parts(&self) -> (i64, u32) {
+            self.and_utc().to_unix_parts()
+        }
+
+        fn from_unix_parts(secs: i64, nanos: u32) -> Option<Self> {
+            DateTime::from_timestamp(secs, nanos).map(|dt| dt.naive_utc())
+        }
+    }
+
+    impl Timestamp for NaiveDate {
+        const DATE_ONLY: bool = true;
+
+        fn to_unix_parts(&self) -> (i64, u32) {
+            (self.and_time(NaiveTime::MIN).and_utc().timestamp(), 0)
+        }
+
+        fn from_unix_parts(secs: i64, _nanos: u32) -> Option<Self> {
+            DateTime::from_timestamp(secs, 0).map(|dt| dt.date_naive())
+        }
+    }
+}
+
+#[cfg(feature = "time")]
+mod time_impls {
+    use super::Timestamp;
+    use time::{Date, OffsetDateTime, PrimitiveDateTime};
+
+    impl Timestamp for OffsetDateTime {
+        fn to_unix_parts(&self) -> (i64, u32) {
+            (self.unix_timestamp(), self.nanosecond())
+        }
+
+        fn from_unix_parts(secs: i64, nanos: u32) -> Option<Self> {
+            OffsetDateTime::from_unix_timestamp(secs).ok()?.replace_nanosecond(nanos).ok()
+        }
+    }
+
+    impl Timestamp for PrimitiveDateTime {
+        fn to_unix_parts(&self) -> (i64, u32) {
+            self.assume_utc().to_unix_parts()
+        }
+
+        fn from_unix_parts(secs: i64, nanos: u32) -> Option<Self> {
+            let dt = OffsetDateTime::from_unix_parts(secs, nanos)?;
+            Some(PrimitiveDateTime::new(dt.date(), dt.time()))
+        }
+    }
+
+    impl Timestamp for Date {
+        const DATE_ONLY: bool = true;
+
+        fn to_unix_parts(&self) -> (i64, u32) {
+            (self.midnight().assume_utc().unix_timestamp(), 0)
+        }
+
+        fn from_unix_parts(secs: i64, _nanos: u32) -> Option<Self> {
+            OffsetDateTime::from_unix_timestamp(secs).ok().map(|dt| dt.date())
+        }
+    }
+}
+
+/// Format unix seconds as `YYYY-MM-DDTHH:MM:SS.nnnnnnnnnZ`, or `None` outside the years 0000 to 9999
+pub(crate) fn format_datetime(secs: i64, nanos: u32) -> Option<String> {
+    let days = secs.div_euclid(SECS_PER_DAY);
+    let secs_of_day = secs.rem_euclid(SECS_PER_DAY);
+    let (year, month, day) = four_digit_year(civil_from_days(days))?;
+    Some(format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:09}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day % 3600 / 60,
+        secs_of_day % 60,
+        nanos
+    ))
+}
+
+/// Format the calendar date of unix seconds as `YYYY-MM-DD`, or `None` outside the years 0000 to 9999
+pub(crate) fn format_date(secs: i64) -> Option<String> {
+    let (year, month, day) = four_digit_year(civil_from_days(secs.div_euclid(SECS_PER_DAY)))?;
+    Some(format!("{:04}-{:02}-{:02}", year, month, day))
+}
+
+/// Keep dates whose year fits the fixed-width four-digit form
+fn four_digit_year(date: (i64, i64, i64)) -> Option<(i64, i64, i64)> {
+    (0..=9999).contains(&date.0).then_some(date)
+}
+
+/// Parse `YYYY-MM-DD` optionally followed by `[T ]HH:MM:SS[.fraction][Z|±HH:MM]`
+fn parse_datetime(s: &str) -> Option<(i64, u32)> {
+    let b = s.as_bytes();
+    let num = |from: usize, len: usize| -> Option<i64> {
+        let digits = b.get(from..from + len)?;
+        if !digits.iter().all(u8::is_ascii_digit) {
+            return None;
+        }
+        std::str::from_utf8(digits).ok()?.parse().ok()
+    };
+
+    let year = num(0, 4)?;
+    let month = num(5, 2)?;
+    let day = num(8, 2)?;
+    if b.get(4) != Some(&b'-') || b.get(7) != Some(&b'-') {
+        return None;
+    }
+    if !(1..=12).contains(&month) || day < 1 || day > days_in_month(year, month) {
+        return None;
+    }
+    let days = days_from_civil(year, month, day);
+    if b.len() == 10 {
+        return Some((days * SECS_PER_DAY, 0));
+    }
+
+    if !matches!(b[10], b'T' | b't' | b' ') || b.get(13) != Some(&b':') || b.get(16) != Some(&b':') {
+        return None;
+    }
+    let hour = num(11, 2)?;
+    let minute = num(14, 2)?;
+    let second = num(17, 2)?;
+    if hour > 23 || minute > 59 || second > 59 {
+        return None;
+    }
+
+    let mut pos = 19;
+    let mut nanos = 0u32;
+    if b.get(pos) == Some(&b'.') {
+        pos += 1;
+        let start = pos;
+        while pos < b.len() && b[pos].is_ascii_digit() {
+            if pos - start < 9 {
+                nanos = nanos * 10 + (b[pos] - b'0') as u32;
+            }
+            pos += 1;
+        }
+        if pos == start {
+            return None;
+        }
+        for _ in (pos - start)..9 {
+            nanos *= 10;
+        }
+    }
+
+    let offset = match b.get(pos) {
+        None => 0,
+        Some(b'Z') | Some(b'z') if pos + 1 == b.len() => 0,
+        Some(&sign @ (b'+' | b'-')) => {
+            let hours = num(pos + 1, 2)?;
+            let (minutes, end) = if b.get(pos + 3) == Some(&b':') {
+                (num(pos + 4, 2)?, pos + 6)
+            } else {
+                (num(pos + 3, 2)?, pos + 5)
+            };
+            if end != b.len() || hours > 23 || minutes > 59 {
+                return None;
+            }
+            let offset = hours * 3600 + minutes * 60;
+            if sign == b'+' { offset } else { -offset }
+        }
+        Some(_) => return None,
+    };
+
+    let secs = days * SECS_PER_DAY + hour * 3600 + minute * 60 + second - offset;
+    Some((secs, nanos))
+}
+
+fn is_leap_year(year: i64) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Days since 1970-01-01 for a proleptic Gregorian date
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y.rem_euclid(400);
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// Proleptic Gregorian date for days since 1970-01-01
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
@@ -1,24 +1,190 @@
-use crate::model::Model;
-use rusqlite::{params_from_iter, Connection, Result as SqliteResult, Row};
-use serde_json;
+use crate::audit::{self, Action, Auditor, HistoryEntry, Target};
+use crate::datetime::{timestamp_value, Clock, SystemClock};
+use crate::logging::{QueryLogger, Tracer};
+use crate::model::{ColumnType, Model};
+use crate::options::DatabaseOptions;
+use crate::search;
+use crate::ser::model_values;
+use crate::statement_cache::{StatementCache, StatementCacheStats};
+use crate::tracked::Tracked;
+use crate::util::{check_field, quote_ident, quote_idents, row_to_struct, DeserializeMode};
+use crate::validate::Validator;
+use rusqlite::types::Value as SqlValue;
+use rusqlite::blob::Blob;
+use rusqlite::{params_from_iter, CachedStatement, Connection, DatabaseName, Params, Result as SqliteResult, Row};
+use serde::de::DeserializeOwned;
+use std::cell::RefCell;
 use std::path::Path;
+use std::sync::Arc;
 
 /// Main database connection wrapper
 pub struct Database {
     pub(crate) conn: Connection,
+    pub(crate) clock: Arc<dyn Clock>,
+    pub(crate) deserialize_mode: DeserializeMode,
+    pub(crate) statements: StatementCache,
+    pub(crate) tracer: Tracer,
+    pub(crate) actor: RefCell<Option<String>>,
 }
 
 impl Database {
     /// Connect to or create a SQLite database file
     pub fn connect<P: AsRef<Path>>(path: P) -> SqliteResult<Self> {
         let conn = Connection::open(path)?;
-        Ok(Database { conn })
+        Ok(Database::from_connection(conn))
     }
 
     /// Connect to an in-memory database (useful for testing)
     pub fn connect_in_memory() -> SqliteResult<Self> {
         let conn = Connection::open_in_memory()?;
-        Ok(Database { conn })
+        Ok(Database::from_connection(conn))
+    }
+
+    /// Connect to a SQLite database file with journal mode, timeouts, pragmas and open flags
+    /// taken from `options`
+    pub fn connect_with_options<P: AsRef<Path>>(path: P, options: &DatabaseOptions) -> SqliteResult<Self> {
+        let conn = options.open(path)?;
+        Ok(Database::from_connection(conn).with_options(options))
+    }
+
+    pub(crate) fn from_connection(conn: Connection) -> Self {
+        Database {
+            conn,
+            clock: Arc::new(SystemClock),
+            deserialize_mode: DeserializeMode::Loose,
+            statements: StatementCache::new(),
+            tracer: Tracer::default(),
+            actor: RefCell::new(None),
+        }
+    }
+
+    /// Use a custom clock for `created_at`/`updated_at` management
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Choose how loaded rows are matched against model field types (defaults to loose)
+    pub fn with_deserialize_mode(mut self, mode: DeserializeMode) -> Self {
+        self.deserialize_mode = mode;
+        self
+    }
+
+    /// Report every statement this database runs, with its parameters, duration and row count
+    pub fn with_query_logger(mut self, logger: impl QueryLogger + 'static) -> Self {
+        self.tracer.logger = Some(Arc::new(logger));
+        self
+    }
+
+    /// Log values bound to these fields as `'[REDACTED]'` instead of their contents
+    pub fn with_redacted_fields(mut self, fields: &[&str]) -> Self {
+        self.tracer.redacted = Arc::new(fields.iter().map(|f| f.to_string()).collect());
+        self
+    }
+
+    /// Apply the settings in `options` that live on the `Database` rather than the connection
+    pub(crate) fn with_options(self, options: &DatabaseOptions) -> Self {
+        match options.statement_cache_capacity_value() {
+            Some(capacity) => self.with_statement_cache_capacity(capacity),
+            None => self,
+        }
+    }
+
+    /// Keep up to `capacity` prepared statements for reuse (defaults to 16, 0 disables caching)
+    pub fn with_statement_cache_capacity(self, capacity: usize) -> Self {
+        self.statements.set_capacity(&self.conn, capacity);
+        self
+    }
+
+    /// Hit and miss counts for the prepared statement cache
+    pub fn statement_cache_stats(&self) -> StatementCacheStats {
+        self.statements.stats()
+    }
+
+    /// Reset the statement cache hit and miss counts to zero
+    pub fn reset_statement_cache_stats(&self) {
+        self.statements.reset_stats();
+    }
+
+    /// Drop all cached prepared statements
+    pub fn clear_statement_cache(&self) {
+        self.statements.clear(&self.conn);
+    }
+
+    /// Prepare generated SQL through the statement cache
+    fn prepare(&self, sql: &str) -> SqliteResult<CachedStatement<'_>> {
+        self.statements.prepare(&self.conn, sql)
+    }
+
+    /// Run generated SQL with each parameter tagged by the field it is bound to
+    fn execute(&self, sql: &str, params: &[(Option<&str>, SqlValue)]) -> SqliteResult<usize> {
+        let started = self.tracer.start();
+        let changed = self.prepare(sql)?.execute(params_from_iter(params.iter().map(|(_, value)| value)))?;
+        self.tracer.finish(started, sql, params.iter().map(|(field, value)| (*field, value)), changed);
+        Ok(changed)
+    }
+
+    /// Run a generated SELECT and load every row
+    fn select<T: Model>(&self, sql: &str, params: &[(Option<&str>, SqlValue)]) -> SqliteResult<Vec<T>> {
+        let started = self.tracer.start();
+        let mut stmt = self.prepare(sql)?;
+        let rows = stmt.query_map(params_from_iter(params.iter().map(|(_, value)| value)), |row| {
+            self.row_to_model::<T>(row)
+        })?;
+
+        let mut results = Vec::new();
+        for row_result in rows {
+            results.push(row_result?);
+        }
+
+        self.tracer.finish(started, sql, params.iter().map(|(field, value)| (*field, value)), results.len());
+        Ok(results)
+    }
+
+    /// Run a write, recording the rows it changes when the model keeps a history table
+    fn audited<T: Model>(
+        &self,
+        action: Action,
+        target: Target,
+        write: impl FnOnce() -> SqliteResult<usize>,
+    ) -> SqliteResult<usize> {
+        match T::history_table() {
+            Some(history) => self.auditor().record::<T>(history, action, target, write),
+            None => write(),
+        }
+    }
+
+    /// The row of `T` whose primary key is `id`
+    fn row_target<T: Model>(&self, id: SqlValue) -> Target {
+        let pk = T::primary_key();
+        Target::Where(format!(" WHERE {} = ?", quote_ident(pk)), vec![(Some(pk.to_string()), id)])
+    }
+
+    fn auditor(&self) -> Auditor<'_> {
+        Auditor {
+            conn: &self.conn,
+            statements: Some(&self.statements),
+            tracer: &self.tracer,
+            actor: self.actor.borrow().clone(),
+            now: self.clock.now(),
+        }
+    }
+
+    /// Run DDL, which is neither cached nor bound
+    fn execute_ddl(&self, sql: &str) -> SqliteResult<()> {
+        let started = self.tracer.start();
+        self.conn.execute(sql, [])?;
+        self.tracer.finish(started, sql, [], 0);
+        Ok(())
+    }
+
+    /// True when a table (including a virtual table) with this name exists
+    fn table_exists(&self, name: &str) -> SqliteResult<bool> {
+        let sql = "SELECT 1 FROM sqlite_master WHERE name = ?";
+        let started = self.tracer.start();
+        let found = self.prepare(sql)?.exists([name])?;
+        self.tracer.finish(started, sql, [(None, &SqlValue::Text(name.to_string()))], found as usize);
+        Ok(found)
     }
 
     /// Create a table for the given model
@@ -27,69 +193,89 @@ impl Database {
         let fields = T::fields();
         
         // Build CREATE TABLE statement
-        // For simplicity, we'll use TEXT for most fields and INTEGER for id
+        // The primary key is always INTEGER, other fields use the model's column types
         let mut field_definitions = Vec::new();
         for field in fields {
             if *field == T::primary_key() {
-                field_definitions.push(format!("{} INTEGER PRIMARY KEY", field));
+                field_definitions.push(format!("{} INTEGER PRIMARY KEY", quote_ident(field)));
             } else {
-                field_definitions.push(format!("{} TEXT", field));
+                let column_type = T::column_type(field);
+                let mut definition = format!("{} {}", quote_ident(field), column_type.sql_type());
+                if let Some(check) = column_type.check_constraint(field) {
+                    definition.push(' ');
+                    definition.push_str(&check);
+                }
+                field_definitions.push(definition);
             }
         }
         
         let sql = format!(
             "CREATE TABLE IF NOT EXISTS {} ({})",
-            table_name,
+            quote_ident(table_name),
             field_definitions.join(", ")
         );
         
-        self.conn.execute(&sql, [])?;
+        self.execute_ddl(&sql)?;
+        
+        if let Some(history) = T::history_table() {
+            for sql in audit::history_ddl(history) {
+                self.execute_ddl(&sql)?;
+            }
+        }
+
+        if !T::search_fields().is_empty() {
+            let indexed = self.table_exists(&search::fts_table(table_name))?;
+            for sql in search::search_ddl::<T>()? {
+                self.execute_ddl(&sql)?;
+            }
+            // Index rows written before the model declared search fields
+            if !indexed {
+                self.execute_ddl(&search::rebuild_sql(table_name))?;
+            }
+        }
         Ok(())
     }
 
     /// Insert a model instance into the database
-    pub fn insert<T: Model>(&self, model: &T) -> SqliteResult<i64> {
+    /// Runs the model's `before_insert` and `after_insert` hooks around the write
+    pub fn insert<T: Model>(&self, model: &mut T) -> SqliteResult<i64> {
+        model.before_insert()?;
         let table_name = T::table_name();
         let fields = T::fields();
         
-        // Serialize model to JSON to extract field values
-        let json_value = serde_json::to_value(model)
-            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+        // Bind field values in `fields()` order
+        let mut values = model_values(model)?;
         
-        let json_obj = json_value.as_object()
-            .ok_or_else(|| rusqlite::Error::InvalidQuery)?;
+        // Fill in managed timestamps
+        let now = self.clock.now();
+        for field in [T::created_at_field(), T::updated_at_field()].into_iter().flatten() {
+            if let Some(idx) = fields.iter().position(|f| *f == field) {
+                values[idx] = timestamp_value(&now, T::column_type(field))?;
+            }
+        }
+        
+        validate(model, &values)?;
         
         // Build field names and placeholders
-        let field_names: Vec<&str> = fields.iter().copied().collect();
         let placeholders: Vec<String> = (0..fields.len()).map(|_| "?".to_string()).collect();
         
-        // Extract values in the correct order
-        let mut values: Vec<String> = Vec::new();
-        for field in fields {
-            let value = json_obj.get(*field)
-                .ok_or_else(|| rusqlite::Error::InvalidQuery)?;
-            
-            // Convert JSON value to string representation
-            let value_str = match value {
-                serde_json::Value::String(s) => s.clone(),
-                serde_json::Value::Number(n) => n.to_string(),
-                serde_json::Value::Bool(b) => b.to_string(),
-                serde_json::Value::Null => "NULL".to_string(),
-                _ => serde_json::to_string(value)
-                    .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?,
-            };
-            values.push(value_str);
-        }
-        
         let sql = format!(
             "INSERT INTO {} ({}) VALUES ({})",
-            table_name,
-            field_names.join(", "),
+            quote_ident(table_name),
+            quote_idents(fields),
             placeholders.join(", ")
         );
         
-        self.conn.execute(&sql, params_from_iter(values.iter()))?;
-        Ok(self.conn.last_insert_rowid())
+        let params: Vec<(Option<&str>, SqlValue)> = fields.iter().map(|f| Some(*f)).zip(values).collect();
+        // Read the rowid before the history row is inserted after it
+        let mut id = 0;
+        self.audited::<T>(Action::Insert, Target::Inserted, || {
+            let changed = self.execute(&sql, &params)?;
+            id = self.conn.last_insert_rowid();
+            Ok(changed)
+        })?;
+        model.after_insert();
+        Ok(id)
     }
 
     /// Select all rows from a model's table
@@ -97,23 +283,22 @@ impl Database {
         let table_name = T::table_name();
         let fields = T::fields();
         
-        let sql = format!(
+        let mut sql = format!(
             "SELECT {} FROM {}",
-            fields.join(", "),
-            table_name
+            quote_idents(fields),
+            quote_ident(table_name)
         );
         
-        let mut stmt = self.conn.prepare(&sql)?;
-        let rows = stmt.query_map([], |row| {
-            self.row_to_model::<T>(row, fields)
-        })?;
-        
-        let mut results = Vec::new();
-        for row_result in rows {
-            results.push(row_result?);
+        if let Some(deleted_at) = T::deleted_at_field() {
+            sql.push_str(&format!(" WHERE {} IS NULL", quote_ident(deleted_at)));
         }
         
-        Ok(results)
+        self.select(&sql, &[])
+    }
+
+    /// Prepare a SELECT of all rows for lazy iteration instead of collecting them
+    pub fn select_iter<T: Model>(&self) -> SqliteResult<crate::stream::RowStream<'_, T>> {
+        self.query::<T>().fetch_iter::<T>()
     }
 
     /// Find a single row by primary key
@@ -122,115 +307,388 @@ impl Database {
         let fields = T::fields();
         let pk = T::primary_key();
         
-        let sql = format!(
+        let mut sql = format!(
             "SELECT {} FROM {} WHERE {} = ?",
-            fields.join(", "),
-            table_name,
-            pk
+            quote_idents(fields),
+            quote_ident(table_name),
+            quote_ident(pk)
         );
         
-        let mut stmt = self.conn.prepare(&sql)?;
-        let mut rows = stmt.query_map([id], |row| {
-            self.row_to_model::<T>(row, fields)
-        })?;
-        
-        if let Some(row_result) = rows.next() {
-            Ok(Some(row_result?))
-        } else {
-            Ok(None)
+        if let Some(deleted_at) = T::deleted_at_field() {
+            sql.push_str(&format!(" AND {} IS NULL", quote_ident(deleted_at)));
         }
+        
+        let rows = self.select(&sql, &[(Some(pk), SqlValue::Integer(id))])?;
+        Ok(rows.into_iter().next())
     }
 
     /// Delete a row by primary key
+    /// Soft-deletable models are marked as deleted instead of being removed
     pub fn delete<T: Model>(&self, id: i64) -> SqliteResult<usize> {
+        T::before_delete(id)?;
+        let deleted_at = match T::deleted_at_field() {
+            Some(field) => field,
+            None => return self.remove::<T>(id),
+        };
+        
+        let sql = format!(
+            "UPDATE {} SET {} = ? WHERE {} = ? AND {} IS NULL",
+            quote_ident(T::table_name()),
+            quote_ident(deleted_at),
+            quote_ident(T::primary_key()),
+            quote_ident(deleted_at)
+        );
+        
+        let now = timestamp_value(&self.clock.now(), T::column_type(deleted_at))?;
+        self.audited::<T>(Action::Delete, self.row_target::<T>(SqlValue::Integer(id)), || {
+            self.execute(&sql, &[(Some(deleted_at), now), (Some(T::primary_key()), SqlValue::Integer(id))])
+        })
+    }
+
+    /// Permanently delete a row by primary key, even for soft-deletable models
+    pub fn force_delete<T: Model>(&self, id: i64) -> SqliteResult<usize> {
+        T::before_delete(id)?;
+        self.remove::<T>(id)
+    }
+
+    fn remove<T: Model>(&self, id: i64) -> SqliteResult<usize> {
         let table_name = T::table_name();
         let pk = T::primary_key();
         
         let sql = format!(
             "DELETE FROM {} WHERE {} = ?",
-            table_name,
-            pk
+            quote_ident(table_name),
+            quote_ident(pk)
+        );
+        
+        self.audited::<T>(Action::Delete, self.row_target::<T>(SqlValue::Integer(id)), || {
+            self.execute(&sql, &[(Some(pk), SqlValue::Integer(id))])
+        })
+    }
+
+    /// Restore a soft-deleted row by primary key
+    pub fn restore<T: Model>(&self, id: i64) -> SqliteResult<usize> {
+        let deleted_at = T::deleted_at_field().ok_or(rusqlite::Error::InvalidQuery)?;
+        
+        let sql = format!(
+            "UPDATE {} SET {} = NULL WHERE {} = ?",
+            quote_ident(T::table_name()),
+            quote_ident(deleted_at),
+            quote_ident(T::primary_key())
         );
         
-        self.conn.execute(&sql, [id])
+        self.audited::<T>(Action::Update, self.row_target::<T>(SqlValue::Integer(id)), || {
+            self.execute(&sql, &[(Some(T::primary_key()), SqlValue::Integer(id))])
+        })
     }
 
     /// Update a model instance in the database
-    pub fn update<T: Model>(&self, model: &T) -> SqliteResult<usize> {
+    /// Runs the model's `before_update` and `after_update` hooks around the write
+    ///
+    /// For models with a `version_field`, the row is only written if its version still
+    /// matches the model's; the version is then incremented in the row and on the model.
+    /// A row that was changed or deleted in the meantime fails with `StaleObject`.
+    pub fn update<T: Model>(&self, model: &mut T) -> SqliteResult<usize> {
+        self.update_columns(model, Columns::All)
+    }
+
+    /// Update only the listed fields, leaving other columns as they are in the row
+    /// Managed `updated_at` and version fields are still written; hooks, validation and
+    /// optimistic locking work as in `update`
+    pub fn update_fields<T: Model>(&self, model: &mut T, fields: &[&str]) -> SqliteResult<usize> {
+        for field in fields {
+            check_field::<T>(field)?;
+        }
+        self.update_columns(model, Columns::Only(fields))
+    }
+
+    /// Write the chosen fields of a model back to its row
+    fn update_columns<T: Model>(&self, model: &mut T, columns: Columns<'_>) -> SqliteResult<usize> {
+        model.before_update()?;
         let table_name = T::table_name();
         let fields = T::fields();
         let pk = T::primary_key();
         
-        // Serialize model to JSON
-        let json_value = serde_json::to_value(model)
-            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+        // Bind field values in `fields()` order
+        let mut field_values = model_values(model)?;
         
-        let json_obj = json_value.as_object()
-            .ok_or_else(|| rusqlite::Error::InvalidQuery)?;
+        // Decided after `before_update`, so fields the hook changes are written too
+        let selected: Vec<bool> = match columns {
+            Columns::All => vec![true; fields.len()],
+            Columns::Only(only) => fields.iter().map(|field| only.contains(field)).collect(),
+            Columns::ChangedSince(original) => field_values.iter().zip(original).map(|(now, then)| now != then).collect(),
+        };
+        if matches!(columns, Columns::ChangedSince(_)) && !selected.contains(&true) {
+            return Ok(0);
+        }
+        
+        if let Some(field) = T::updated_at_field() {
+            if let Some(idx) = fields.iter().position(|f| *f == field) {
+                field_values[idx] = timestamp_value(&self.clock.now(), T::column_type(field))?;
+            }
+        }
+        
+        validate(model, &field_values)?;
         
         // Get primary key value
-        let pk_value = json_obj.get(pk)
+        let pk_idx = fields.iter().position(|f| *f == pk)
             .ok_or_else(|| rusqlite::Error::InvalidQuery)?;
-        let pk_str = match pk_value {
-            serde_json::Value::Number(n) => n.to_string(),
-            serde_json::Value::String(s) => s.clone(),
+        let pk_sql = match &field_values[pk_idx] {
+            value @ (SqlValue::Integer(_) | SqlValue::Text(_)) => value.clone(),
             _ => return Err(rusqlite::Error::InvalidQuery),
         };
         
+        // Write the next version, but only over the version this model was loaded with
+        let version = match T::version_field() {
+            Some(field) => {
+                let idx = fields.iter().position(|f| *f == field)
+                    .ok_or_else(|| rusqlite::Error::InvalidColumnName(field.to_string()))?;
+                let current = match field_values[idx] {
+                    SqlValue::Integer(version) => version,
+                    _ => return Err(rusqlite::Error::InvalidQuery),
+                };
+                field_values[idx] = SqlValue::Integer(current + 1);
+                // A model that does not store the new version would be stale after this update
+                model.set_version(current + 1);
+                if model_values(model)?[idx] != field_values[idx] {
+                    model.set_version(current);
+                    return Err(rusqlite::Error::InvalidQuery);
+                }
+                Some((field, current))
+            }
+            None => None,
+        };
+        
         // Build SET clause (excluding primary key)
         let mut set_clauses = Vec::new();
-        let mut values: Vec<String> = Vec::new();
+        let mut values = Vec::new();
         
-        for field in fields {
-            if *field == pk {
-                continue; // Skip primary key in UPDATE SET
+        for ((field, value), selected) in fields.iter().zip(field_values).zip(selected) {
+            if *field == pk || Some(*field) == T::created_at_field() {
+                continue; // Skip primary key and creation time in UPDATE SET
+            }
+            let managed = Some(*field) == T::updated_at_field() || Some(*field) == T::version_field();
+            if !managed && !selected {
+                continue;
             }
             
-            set_clauses.push(format!("{} = ?", field));
-            
-            let value = json_obj.get(*field)
-                .ok_or_else(|| rusqlite::Error::InvalidQuery)?;
-            
-            let value_str = match value {
-                serde_json::Value::String(s) => s.clone(),
-                serde_json::Value::Number(n) => n.to_string(),
-                serde_json::Value::Bool(b) => b.to_string(),
-                serde_json::Value::Null => "NULL".to_string(),
-                _ => serde_json::to_string(value)
-                    .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?,
-            };
-            values.push(value_str);
+            set_clauses.push(format!("{} = ?", quote_ident(field)));
+            values.push((Some(*field), value));
+        }
+        
+        if set_clauses.is_empty() {
+            return Err(rusqlite::Error::InvalidQuery);
         }
         
         // Add primary key value for WHERE clause
-        values.push(pk_str);
+        values.push((Some(pk), pk_sql.clone()));
         
-        let sql = format!(
+        let mut sql = format!(
             "UPDATE {} SET {} WHERE {} = ?",
-            table_name,
+            quote_ident(table_name),
             set_clauses.join(", "),
-            pk
+            quote_ident(pk)
         );
         
-        self.conn.execute(&sql, params_from_iter(values.iter()))
+        if let Some((field, current)) = version {
+            sql.push_str(&format!(" AND {} = ?", quote_ident(field)));
+            values.push((Some(field), SqlValue::Integer(current)));
+        }
+        
+        let target = self.row_target::<T>(pk_sql.clone());
+        let written = self.audited::<T>(Action::Update, target, || self.execute(&sql, &values));
+        let changed = match (written, version) {
+            (Ok(0), Some((_, current))) => {
+                model.set_version(current);
+                return Err(StaleObject { table: table_name, id: pk_sql, version: current }.into());
+            }
+            (Ok(changed), _) => changed,
+            (Err(e), version) => {
+                if let Some((_, current)) = version {
+                    model.set_version(current);
+                }
+                return Err(e);
+            }
+        };
+        model.after_update();
+        Ok(changed)
     }
 
     /// Helper to convert a Row to a Model instance
-    fn row_to_model<T: Model>(&self, row: &Row, fields: &[&str]) -> SqliteResult<T> {
-        crate::util::row_to_model(row, fields)
+    fn row_to_model<T: Model>(&self, row: &Row) -> SqliteResult<T> {
+        crate::util::row_to_model(row, self.deserialize_mode)
+    }
+
+    /// Replace a BLOB field with `len` zero bytes so it can be filled through `open_blob`
+    pub fn reserve_blob<T: Model>(&self, field: &str, id: i64, len: usize) -> SqliteResult<usize> {
+        check_field::<T>(field)?;
+        let sql = format!(
+            "UPDATE {} SET {} = zeroblob(?) WHERE {} = ?",
+            quote_ident(T::table_name()),
+            quote_ident(field),
+            quote_ident(T::primary_key())
+        );
+        
+        self.audited::<T>(Action::Update, self.row_target::<T>(SqlValue::Integer(id)), || {
+            self.execute(&sql, &[(None, SqlValue::Integer(len as i64)), (Some(T::primary_key()), SqlValue::Integer(id))])
+        })
+    }
+
+    /// Open a BLOB field for incremental reading and writing
+    /// The returned handle implements `Read`, `Write` and `Seek`; writes cannot grow the blob
+    pub fn open_blob<T: Model>(&self, field: &str, id: i64, read_only: bool) -> SqliteResult<Blob<'_>> {
+        check_field::<T>(field)?;
+        self.conn.blob_open(DatabaseName::Main, T::table_name(), field, id, read_only)
     }
 
     /// Drop a table (useful for testing)
     pub fn drop_table<T: Model>(&self) -> SqliteResult<()> {
         let table_name = T::table_name();
-        let sql = format!("DROP TABLE IF EXISTS {}", table_name);
-        self.conn.execute(&sql, [])?;
+        let sql = format!("DROP TABLE IF EXISTS {}", quote_ident(table_name));
+        self.execute_ddl(&sql)?;
+
+        if !T::search_fields().is_empty() {
+            let sql = format!("DROP TABLE IF EXISTS {}", quote_ident(&search::fts_table(table_name)));
+            self.execute_ddl(&sql)?;
+        }
         Ok(())
     }
 
+    /// Roll back a transaction left open, e.g. by `raw_execute("BEGIN")`, so the
+    /// connection can be handed to someone else
+    pub(crate) fn rollback_open_transaction(&self) -> SqliteResult<()> {
+        if !self.conn.is_autocommit() {
+            self.execute_ddl("ROLLBACK")?;
+        }
+        Ok(())
+    }
+
+    /// Record `actor` (for example a user id) on history entries written from now on
+    /// Pooled connections forget their actor when they are returned
+    pub fn set_actor(&self, actor: Option<&str>) {
+        *self.actor.borrow_mut() = actor.map(str::to_string);
+    }
+
+    /// Recorded changes to one row of a model with a `history_table`, oldest first
+    pub fn history<T: Model>(&self, id: i64) -> SqliteResult<Vec<HistoryEntry>> {
+        let history = T::history_table().ok_or(rusqlite::Error::InvalidQuery)?;
+        self.auditor().history(history, id)
+    }
+
+    /// Write the fields changed since a tracked model was loaded or last saved,
+    /// including any the `before_update` hook changes
+    /// Returns 0 without touching the database when nothing changed
+    pub fn save_changes<T: Model>(&self, tracked: &mut Tracked<T>) -> SqliteResult<usize> {
+        if !tracked.is_changed()? {
+            return Ok(0);
+        }
+        let rows = self.update_columns(&mut tracked.model, Columns::ChangedSince(&tracked.original))?;
+        tracked.snapshot()?;
+        Ok(rows)
+    }
+
+    /// Run hand-written SQL and map each row onto a model by column name
+    ///
+    /// Columns may come in any order and unknown ones are ignored, so `SELECT *`,
+    /// joins and computed columns work; a missing field fails with `InvalidColumnName`.
+    /// Enum and decimal columns are decoded with the model's column types.
+    /// `params` takes anything rusqlite accepts, e.g. `[1]`, `params![..]` or
+    /// `named_params! { ":name": .. }`; raw parameters are not included in query logs.
+    pub fn raw_query<T: Model>(&self, sql: &str, params: impl Params) -> SqliteResult<Vec<T>> {
+        let started = self.tracer.start();
+        let mut stmt = self.prepare(sql)?;
+        let rows = stmt.query_map(params, |row| self.row_to_model::<T>(row))?;
+        let results = rows.collect::<SqliteResult<Vec<T>>>()?;
+        self.tracer.finish(started, sql, [], results.len());
+        Ok(results)
+    }
+
+    /// Run hand-written SQL and map each row onto any deserializable struct by column name
+    /// Use this for results that are not a model, like aggregates or report rows
+    pub fn raw_query_as<T: DeserializeOwned>(&self, sql: &str, params: impl Params) -> SqliteResult<Vec<T>> {
+        let started = self.tracer.start();
+        let mut stmt = self.prepare(sql)?;
+        let rows = stmt.query_map(params, |row| row_to_struct(row, |_| ColumnType::Text, None, self.deserialize_mode))?;
+        let results = rows.collect::<SqliteResult<Vec<T>>>()?;
+        self.tracer.finish(started, sql, [], results.len());
+        Ok(results)
+    }
+
+    /// Run a hand-written statement and return the number of rows changed
+    pub fn raw_execute(&self, sql: &str, params: impl Params) -> SqliteResult<usize> {
+        let started = self.tracer.start();
+        let changed = self.prepare(sql)?.execute(params)?;
+        self.tracer.finish(started, sql, [], changed);
+        Ok(changed)
+    }
+
     /// Create a query builder for this database
     pub fn query<T: Model>(&self) -> crate::query::QueryBuilder<'_> {
-        crate::query::QueryBuilder::new::<T>(&self.conn)
+        let mut builder = crate::query::QueryBuilder::new::<T>(&self.conn);
+        builder.clock = Arc::clone(&self.clock);
+        builder.deserialize_mode = self.deserialize_mode;
+        builder.statements = Some(&self.statements);
+        builder.tracer = self.tracer.clone();
+        builder.actor = self.actor.borrow().clone();
+        builder
     }
 }
 
+/// Which fields `update_columns` writes, besides the managed `updated_at` and version fields
+#[derive(Clone, Copy)]
+enum Columns<'c> {
+    All,
+    Only(&'c [&'c str]),
+    /// Fields whose values differ from a snapshot, compared after `before_update`
+    ChangedSince(&'c [SqlValue]),
+}
+
+/// Error returned by `update` when a versioned row no longer has the version the model
+/// was loaded with, because another writer updated or deleted it first
+///
+/// It is boxed in `rusqlite::Error::ToSqlConversionFailure`; use `StaleObject::from_error`
+/// to recognise it, then reload the row and retry.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StaleObject {
+    pub table: &'static str,
+    /// Primary key of the row
+    pub id: SqlValue,
+    /// The version the update expected to find
+    pub version: i64,
+}
+
+impl StaleObject {
+    /// Get the conflict out of an error returned by `update`
+    pub fn from_error(err: &rusqlite::Error) -> Option<&StaleObject> {
+        match err {
+            rusqlite::Error::ToSqlConversionFailure(e) => e.downcast_ref(),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for StaleObject {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let id = match &self.id {
+            SqlValue::Integer(i) => i.to_string(),
+            SqlValue::Text(s) => format!("{:?}", s),
+            other => format!("{:?}", other),
+        };
+        write!(f, "stale {} row {}: it no longer has version {}", self.table, id, self.version)
+    }
+}
+
+impl std::error::Error for StaleObject {}
+
+impl From<StaleObject> for rusqlite::Error {
+    fn from(err: StaleObject) -> Self {
+        rusqlite::Error::ToSqlConversionFailure(Box::new(err))
+    }
+}
+
+/// Run the model's validation rules against the values about to be written
+fn validate<T: Model>(model: &T, values: &[SqlValue]) -> SqliteResult<()> {
+    let mut validator = Validator::new(T::fields(), values, T::column_type);
+    model.validate(&mut validator);
+    validator.finish()
+}
@@ -0,0 +1,91 @@
+//! Exact decimal columns
+//!
+//! `ColumnType::Decimal(scale)` stores decimal values as INTEGERs scaled by
+//! `10^scale`, so `19.99` in a `Decimal(2)` column is stored as `1999`. Values
+//! stay exact, and SQL comparisons, `ORDER BY` and `SUM` work numerically.
+//! Writing a value with more decimal places than the column allows is an error
+//! rather than a silent rounding.
+//!
+//! Any field that serializes as a decimal string works, such as `String` or
+//! `rust_decimal::Decimal` (re-exported here with the `rust_decimal` feature):
+//!
+//! ```rust
+//! use pebble::{ColumnType, Model};
+//! use serde::{Deserialize, Serialize};
+//!
+//! #[derive(Serialize, Deserialize)]
+//! struct Invoice {
+//!     id: i32,
+//!     total: String,
+//! }
+//!
+//! impl Model for Invoice {
+//!     fn table_name() -> &'static str {
+//!         "invoices"
+//!     }
+//!
+//!     fn fields() -> &'static [&'static str] {
+//!         &["id", "total"]
+//!     }
+//!
+//!     fn column_type(field: &str) -> ColumnType {
+//!         match field {
+//!             "total" => ColumnType::Decimal(2),
+//!             _ => ColumnType::Text,
+//!         }
+//!     }
+//! }
+//!
+//! assert_eq!(pebble::decimal::to_scaled("19.99", 2), Some(1999));
+//! assert_eq!(pebble::decimal::from_scaled(-5, 2), "-0.05");
+//! ```
+
+#[cfg(feature = "rust_decimal")]
+pub use rust_decimal::Decimal;
+
+/// Parse decimal text into an integer scaled by `10^scale`
+///
+/// Returns `None` for malformed text, values with more than `scale` significant
+/// decimal places and values outside the `i64` range.
+pub fn to_scaled(text: &str, scale: u32) -> Option<i64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text.strip_prefix('+').unwrap_or(text)),
+    };
+    let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+    if whole.is_empty() && fraction.is_empty() {
+        return None;
+    }
+    if !whole.bytes().chain(fraction.bytes()).all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    // Trailing zeros beyond the scale do not change the value
+    let fraction = fraction.trim_end_matches('0');
+    if fraction.len() > scale as usize {
+        return None;
+    }
+
+    let mut value: i64 = 0;
+    let padding = std::iter::repeat_n(b'0', scale as usize - fraction.len());
+    for digit in whole.bytes().chain(fraction.bytes()).chain(padding) {
+        let digit = i64::from(digit - b'0');
+        value = value.checked_mul(10)?;
+        value = if negative { value.checked_sub(digit)? } else { value.checked_add(digit)? };
+    }
+    Some(value)
+}
+
+/// Format an integer scaled by `10^scale` as canonical decimal text
+pub fn from_scaled(value: i64, scale: u32) -> String {
+    let digits = value.unsigned_abs().to_string();
+    let scale = scale as usize;
+    let sign = if value < 0 { "-" } else { "" };
+    if scale == 0 {
+        return format!("{}{}", sign, digits);
+    }
+
+    let digits = format!("{:0>width$}", digits, width = scale + 1);
+    let (whole, fraction) = digits.split_at(digits.len() - scale);
+    format!("{}{}.{}", sign, whole, fraction)
+}
@@ -4,7 +4,7 @@
 //!
 //! ## Example
 //!
-//! ```rust
+//! ```rust,no_run
 //! use pebble::{Model, Database};
 //! use serde::{Serialize, Deserialize};
 //!
@@ -29,13 +29,13 @@
 //!     let db = Database::connect("pebble.db")?;
 //!     db.create_table::<User>()?;
 //!
-//!     let user = User {
+//!     let mut user = User {
 //!         id: 1,
 //!         name: "Alice".into(),
 //!         email: "alice@example.com".into(),
 //!     };
 //!
-//!     db.insert(&user)?;
+//!     db.insert(&mut user)?;
 //!     let users = db.select_all::<User>()?;
 //!
 //!     println!("{:?}", users);
@@ -43,18 +43,46 @@
 //! }
 //! ```
 
+#[cfg(feature = "async")]
+mod async_db;
+mod audit;
+pub mod datetime;
 mod db;
+pub mod decimal;
+mod json;
+pub mod logging;
 mod model;
+mod options;
+mod plan;
+mod pool;
 mod query;
+mod search;
+mod ser;
+mod statement_cache;
+mod stream;
+mod tracked;
 mod util;
+pub mod validate;
 
 #[cfg(test)]
 mod tests;
 
 // Re-export main types
-pub use db::Database;
-pub use model::Model;
+#[cfg(feature = "async")]
+pub use async_db::AsyncDatabase;
+pub use audit::{Action, HistoryEntry};
+pub use db::{Database, StaleObject};
+pub use json::Json;
+pub use model::{ColumnType, Model};
+pub use options::{DatabaseOptions, JournalMode, Synchronous};
+pub use plan::{PlanNode, QueryPlan};
+pub use pool::{Pool, PoolBuilder, PooledDatabase};
 pub use query::QueryBuilder;
+pub use search::SearchHit;
+pub use statement_cache::StatementCacheStats;
+pub use stream::{RowChunks, RowIter, RowStream};
+pub use tracked::Tracked;
+pub use util::DeserializeMode;
 
 // Re-export rusqlite Result type for convenience
 pub use rusqlite::Result;
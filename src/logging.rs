@@ -0,0 +1,155 @@
+//! Query logging
+//!
+//! A `QueryLogger` attached with `Database::with_query_logger` sees every
+//! statement Pebble runs: CRUD methods, query builder fetches and bulk updates,
+//! and the savepoints, snapshots and history rows written for audited models.
+//! Parameters are rendered as SQL literals, with values bound to fields named in
+//! `Database::with_redacted_fields` replaced by `'[REDACTED]'`; those fields are
+//! masked inside audit snapshots too.
+//!
+//! ```rust
+//! use pebble::Database;
+//! use pebble::logging::QueryEvent;
+//!
+//! let db = Database::connect_in_memory()
+//!     .unwrap()
+//!     .with_redacted_fields(&["password"])
+//!     .with_query_logger(|event: &QueryEvent<'_>| {
+//!         eprintln!("{} {:?} -> {} rows in {:?}", event.sql, event.params, event.rows, event.duration);
+//!     });
+//! ```
+//!
+//! The `log` and `tracing` features add ready-made loggers that emit events
+//! under the `pebble` target.
+
+use rusqlite::types::Value as SqlValue;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// A statement run by Pebble
+#[derive(Debug, Clone)]
+pub struct QueryEvent<'e> {
+    /// The SQL text with `?` placeholders
+    pub sql: &'e str,
+    /// Bound parameters rendered as SQL literals, in placeholder order
+    pub params: &'e [String],
+    /// Time spent preparing and running the statement (and loading rows for SELECTs)
+    pub duration: Duration,
+    /// Rows changed by writes, or rows returned by SELECTs
+    pub rows: usize,
+}
+
+/// Receives an event for every statement a `Database` runs
+pub trait QueryLogger: Send + Sync {
+    fn log(&self, event: &QueryEvent<'_>);
+}
+
+impl<F: Fn(&QueryEvent<'_>) + Send + Sync> QueryLogger for F {
+    fn log(&self, event: &QueryEvent<'_>) {
+        self(event)
+    }
+}
+
+/// Logs statements through the `log` crate at debug level
+#[cfg(feature = "log")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LogLogger;
+
+#[cfg(feature = "log")]
+impl QueryLogger for LogLogger {
+    fn log(&self, event: &QueryEvent<'_>) {
+        log::debug!(
+            target: "pebble",
+            "{} [{}] ({} rows in {:?})",
+            event.sql,
+            event.params.join(", "),
+            event.rows,
+            event.duration
+        );
+    }
+}
+
+/// Emits a `tracing` event at debug level for every statement
+#[cfg(feature = "tracing")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TracingLogger;
+
+#[cfg(feature = "tracing")]
+impl QueryLogger for TracingLogger {
+    fn log(&self, event: &QueryEvent<'_>) {
+        tracing::debug!(
+            target: "pebble",
+            sql = event.sql,
+            params = ?event.params,
+            rows = event.rows,
+            duration_us = event.duration.as_micros() as u64,
+            "query"
+        );
+    }
+}
+
+/// Logger and redaction settings shared by a database and its query builders
+#[derive(Clone, Default)]
+pub(crate) struct Tracer {
+    pub(crate) logger: Option<Arc<dyn QueryLogger>>,
+    pub(crate) redacted: Arc<Vec<String>>,
+}
+
+impl Tracer {
+    /// Start timing a statement, or `None` when nobody is listening
+    pub(crate) fn start(&self) -> Option<Instant> {
+        self.logger.as_ref().map(|_| Instant::now())
+    }
+
+    /// Report a finished statement; `params` pairs each value with the field it is bound to
+    pub(crate) fn finish<'p>(
+        &self,
+        started: Option<Instant>,
+        sql: &str,
+        params: impl IntoIterator<Item = (Option<&'p str>, &'p SqlValue)>,
+        rows: usize,
+    ) {
+        let (Some(logger), Some(started)) = (&self.logger, started) else {
+            return;
+        };
+        let duration = started.elapsed();
+
+        let params: Vec<String> = params
+            .into_iter()
+            .map(|(field, value)| match field {
+                Some(field) if self.redacted.iter().any(|redacted| redacted == field) => {
+                    "'[REDACTED]'".to_string()
+                }
+                _ => render(value),
+            })
+            .collect();
+
+        logger.log(&QueryEvent { sql, params: &params, duration, rows });
+    }
+
+    /// Mask redacted fields inside a JSON object of field values, such as an audit snapshot
+    pub(crate) fn redact_object(&self, json: &str) -> String {
+        match serde_json::from_str::<serde_json::Value>(json) {
+            Ok(serde_json::Value::Object(mut object)) => {
+                for (field, value) in object.iter_mut() {
+                    if self.redacted.iter().any(|redacted| redacted == field) {
+                        *value = serde_json::Value::String("[REDACTED]".to_string());
+                    }
+                }
+                serde_json::Value::Object(object).to_string()
+            }
+            _ => json.to_string(),
+        }
+    }
+}
+
+/// Render a bound value as a SQL literal
+fn render(value: &SqlValue) -> String {
+    match value {
+        SqlValue::Null => "NULL".to_string(),
+        SqlValue::Integer(i) => i.to_string(),
+        SqlValue::Real(f) => f.to_string(),
+        SqlValue::Text(s) => format!("'{}'", s.replace('\'', "''")),
+        SqlValue::Blob(b) => format!("<{} bytes>", b.len()),
+    }
+}
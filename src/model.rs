@@ -1,16 +1,138 @@
 use serde::{Deserialize, Serialize};
 
+/// SQLite column type used when creating a model's table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnType {
+    /// INTEGER affinity (integers, unix timestamps)
+    Integer,
+    /// REAL affinity (floating point numbers)
+    Real,
+    /// TEXT affinity (strings, ISO-8601 timestamps)
+    Text,
+    /// BLOB affinity (`Vec<u8>` and other byte buffers, stored as raw bytes)
+    Blob,
+    /// Unit-variant enum stored by variant name, restricted to the listed names
+    TextEnum(&'static [&'static str]),
+    /// Unit-variant enum stored as an integer, mapping each variant name to its discriminant
+    IntegerEnum(&'static [(&'static str, i64)]),
+    /// Exact decimal stored as an integer scaled by `10^scale` (see `pebble::decimal`)
+    Decimal(u32),
+}
+
+impl ColumnType {
+    /// Returns the SQL type name used in CREATE TABLE
+    pub fn sql_type(&self) -> &'static str {
+        match self {
+            ColumnType::Integer | ColumnType::IntegerEnum(_) | ColumnType::Decimal(_) => "INTEGER",
+            ColumnType::Real => "REAL",
+            ColumnType::Text | ColumnType::TextEnum(_) => "TEXT",
+            ColumnType::Blob => "BLOB",
+        }
+    }
+
+    /// Returns the CHECK constraint restricting a column to its allowed values
+    pub fn check_constraint(&self, field: &str) -> Option<String> {
+        let allowed: Vec<String> = match self {
+            ColumnType::TextEnum(names) => names
+                .iter()
+                .map(|name| format!("'{}'", name.replace('\'', "''")))
+                .collect(),
+            ColumnType::IntegerEnum(variants) => variants
+                .iter()
+                .map(|(_, discriminant)| discriminant.to_string())
+                .collect(),
+            _ => return None,
+        };
+        Some(format!("CHECK ({} IN ({}))", crate::util::quote_ident(field), allowed.join(", ")))
+    }
+}
+
 /// Core trait that all models must implement to map to database tables
 pub trait Model: Sized + Serialize + for<'de> Deserialize<'de> {
     /// Returns the name of the database table
     fn table_name() -> &'static str;
-    
+
     /// Returns the field names for the model
     fn fields() -> &'static [&'static str];
-    
+
     /// Returns the primary key field name (defaults to "id")
     fn primary_key() -> &'static str {
         "id"
     }
-}
 
+    /// Returns the column type for a non-primary-key field (defaults to TEXT)
+    fn column_type(_field: &str) -> ColumnType {
+        ColumnType::Text
+    }
+
+    /// Returns the field Pebble sets when a row is inserted (defaults to none)
+    fn created_at_field() -> Option<&'static str> {
+        None
+    }
+
+    /// Returns the field Pebble sets when a row is inserted or updated (defaults to none)
+    fn updated_at_field() -> Option<&'static str> {
+        None
+    }
+
+    /// Returns the field that marks a row as soft-deleted (defaults to none)
+    /// When set, `delete` fills it in instead of removing the row and reads skip such rows
+    fn deleted_at_field() -> Option<&'static str> {
+        None
+    }
+
+    /// Returns the INTEGER field used for optimistic locking (defaults to none)
+    /// When set, `update` only writes rows whose version matches the model's and increments it.
+    /// The model must also implement `set_version`; otherwise `update` fails with `InvalidQuery`
+    /// before writing, since the model would be stale after every update
+    fn version_field() -> Option<&'static str> {
+        None
+    }
+
+    /// Called by `update` with the new version of a versioned row, and with the old one
+    /// again if the write fails; store it in the version field
+    fn set_version(&mut self, _version: i64) {}
+
+    /// Returns the table recording every change to this model's rows (defaults to none)
+    /// `create_table` creates it; inserts, updates and deletes made through Pebble add
+    /// entries with the old and new values as JSON, read back with `Database::history`
+    fn history_table() -> Option<&'static str> {
+        None
+    }
+
+    /// Returns the text fields indexed for full-text search (defaults to none)
+    /// `create_table` creates an FTS5 table `<table>_fts` over them, kept in sync by triggers,
+    /// and `QueryBuilder::search` queries it
+    fn search_fields() -> &'static [&'static str] {
+        &[]
+    }
+
+    /// Called by `insert` before the row is written; an error aborts the insert
+    fn before_insert(&mut self) -> rusqlite::Result<()> {
+        Ok(())
+    }
+
+    /// Called by `insert` after the row is written
+    fn after_insert(&mut self) {}
+
+    /// Called by `update` before the row is written; an error aborts the update
+    fn before_update(&mut self) -> rusqlite::Result<()> {
+        Ok(())
+    }
+
+    /// Called by `update` after the row is written
+    fn after_update(&mut self) {}
+
+    /// Called by `delete` and `force_delete` with the primary key of the row to remove;
+    /// an error aborts the delete
+    fn before_delete(_id: i64) -> rusqlite::Result<()> {
+        Ok(())
+    }
+
+    /// Called on every model loaded from the database
+    fn after_load(&mut self) {}
+
+    /// Declare validation rules, checked by `insert` and `update` after the `before_*` hooks
+    /// Every failing rule is reported together in a `ValidationErrors`
+    fn validate(&self, _v: &mut crate::validate::Validator<'_>) {}
+}
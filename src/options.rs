@@ -0,0 +1,232 @@
+use rusqlite::{Connection, OpenFlags, Result as SqliteResult};
+use std::path::Path;
+use std::time::Duration;
+
+/// SQLite journal mode (`PRAGMA journal_mode`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JournalMode {
+    Delete,
+    Truncate,
+    Persist,
+    Memory,
+    /// Write-ahead logging: readers no longer block writers and vice versa
+    Wal,
+    Off,
+}
+
+impl JournalMode {
+    fn as_str(&self) -> &'static str {
+        match self {
+            JournalMode::Delete => "DELETE",
+            JournalMode::Truncate => "TRUNCATE",
+            JournalMode::Persist => "PERSIST",
+            JournalMode::Memory => "MEMORY",
+            JournalMode::Wal => "WAL",
+            JournalMode::Off => "OFF",
+        }
+    }
+}
+
+/// SQLite durability level (`PRAGMA synchronous`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Synchronous {
+    Off,
+    /// Safe with WAL and much faster than `Full`
+    Normal,
+    Full,
+    Extra,
+}
+
+impl Synchronous {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Synchronous::Off => "OFF",
+            Synchronous::Normal => "NORMAL",
+            Synchronous::Full => "FULL",
+            Synchronous::Extra => "EXTRA",
+        }
+    }
+}
+
+/// Settings applied when opening a database connection
+///
+/// Anything left unset keeps SQLite's default. Pass `":memory:"` as the path to
+/// open an in-memory database with these options.
+#[derive(Debug, Clone)]
+pub struct DatabaseOptions {
+    journal_mode: Option<JournalMode>,
+    synchronous: Option<Synchronous>,
+    busy_timeout: Option<Duration>,
+    foreign_keys: Option<bool>,
+    cache_size: Option<i64>,
+    mmap_size: Option<u64>,
+    read_only: bool,
+    create: bool,
+    statement_cache_capacity: Option<usize>,
+    pragmas: Vec<(String, String)>,
+}
+
+impl Default for DatabaseOptions {
+    fn default() -> Self {
+        DatabaseOptions {
+            journal_mode: None,
+            synchronous: None,
+            busy_timeout: None,
+            foreign_keys: None,
+            cache_size: None,
+            mmap_size: None,
+            read_only: false,
+            create: true,
+            statement_cache_capacity: None,
+            pragmas: Vec::new(),
+        }
+    }
+}
+
+impl DatabaseOptions {
+    /// Start from SQLite's defaults
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the journal mode, e.g. `JournalMode::Wal` for concurrent readers and writers
+    pub fn journal_mode(mut self, mode: JournalMode) -> Self {
+        self.journal_mode = Some(mode);
+        self
+    }
+
+    /// Set how often SQLite waits for data to reach disk
+    pub fn synchronous(mut self, level: Synchronous) -> Self {
+        self.synchronous = Some(level);
+        self
+    }
+
+    /// Wait up to `timeout` for a lock instead of failing with SQLITE_BUSY
+    pub fn busy_timeout(mut self, timeout: Duration) -> Self {
+        self.busy_timeout = Some(timeout);
+        self
+    }
+
+    /// Enforce foreign key constraints
+    pub fn foreign_keys(mut self, enabled: bool) -> Self {
+        self.foreign_keys = Some(enabled);
+        self
+    }
+
+    /// Set the page cache size: positive values count pages, negative values KiB
+    pub fn cache_size(mut self, size: i64) -> Self {
+        self.cache_size = Some(size);
+        self
+    }
+
+    /// Set the number of bytes of the database file to memory-map (0 disables mmap)
+    pub fn mmap_size(mut self, bytes: u64) -> Self {
+        self.mmap_size = Some(bytes);
+        self
+    }
+
+    /// Open the database read-only; writes fail and the file must already exist
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    /// Create the database file if it does not exist (defaults to true)
+    pub fn create(mut self, create: bool) -> Self {
+        self.create = create;
+        self
+    }
+
+    /// Keep up to `capacity` prepared statements for reuse (defaults to 16, 0 disables caching)
+    pub fn statement_cache_capacity(mut self, capacity: usize) -> Self {
+        self.statement_cache_capacity = Some(capacity);
+        self
+    }
+
+    pub(crate) fn statement_cache_capacity_value(&self) -> Option<usize> {
+        self.statement_cache_capacity
+    }
+
+    /// Apply an arbitrary `PRAGMA name = value` after the built-in settings
+    pub fn pragma(mut self, name: &str, value: impl ToString) -> Self {
+        self.pragmas.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    fn open_flags(&self) -> OpenFlags {
+        let mut flags = OpenFlags::SQLITE_OPEN_URI | OpenFlags::SQLITE_OPEN_NO_MUTEX;
+        if self.read_only {
+            flags |= OpenFlags::SQLITE_OPEN_READ_ONLY;
+        } else {
+            flags |= OpenFlags::SQLITE_OPEN_READ_WRITE;
+            if self.create {
+                flags |= OpenFlags::SQLITE_OPEN_CREATE;
+            }
+        }
+        flags
+    }
+
+    /// Options for the read-only connections of a single-writer pool
+    /// The journal mode is persistent, so it is left to the writer to set
+    pub(crate) fn for_readers(&self) -> Self {
+        DatabaseOptions {
+            journal_mode: None,
+            read_only: true,
+            ..self.clone()
+        }
+    }
+
+    /// Open a connection and apply these options to it
+    pub(crate) fn open<P: AsRef<Path>>(&self, path: P) -> SqliteResult<Connection> {
+        let conn = Connection::open_with_flags(path, self.open_flags())?;
+        self.apply(&conn)?;
+        Ok(conn)
+    }
+
+    fn apply(&self, conn: &Connection) -> SqliteResult<()> {
+        if let Some(timeout) = self.busy_timeout {
+            conn.busy_timeout(timeout)?;
+        }
+        if let Some(mode) = self.journal_mode {
+            set_pragma(conn, "journal_mode", mode.as_str())?;
+        }
+        if let Some(level) = self.synchronous {
+            set_pragma(conn, "synchronous", level.as_str())?;
+        }
+        if let Some(enabled) = self.foreign_keys {
+            set_pragma(conn, "foreign_keys", if enabled { "ON" } else { "OFF" })?;
+        }
+        if let Some(size) = self.cache_size {
+            set_pragma(conn, "cache_size", &size.to_string())?;
+        }
+        if let Some(bytes) = self.mmap_size {
+            set_pragma(conn, "mmap_size", &bytes.to_string())?;
+        }
+        for (name, value) in &self.pragmas {
+            set_pragma(conn, name, value)?;
+        }
+        Ok(())
+    }
+}
+
+/// Run `PRAGMA name = value`, ignoring any rows it reports back
+/// Pragma values cannot be bound as parameters, so names must be plain identifiers
+/// and values other than integers are quoted as string literals
+fn set_pragma(conn: &Connection, name: &str, value: &str) -> SqliteResult<()> {
+    let valid_name = !name.is_empty()
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.');
+    if !valid_name {
+        return Err(rusqlite::Error::InvalidQuery);
+    }
+
+    let value = if value.parse::<i64>().is_ok() {
+        value.to_string()
+    } else {
+        format!("'{}'", value.replace('\'', "''"))
+    };
+
+    let mut stmt = conn.prepare(&format!("PRAGMA {} = {}", name, value))?;
+    let mut rows = stmt.query([])?;
+    while rows.next()?.is_some() {}
+    Ok(())
+}
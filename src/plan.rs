@@ -0,0 +1,81 @@
+use std::fmt;
+
+/// The plan SQLite chose for a query, as reported by `EXPLAIN QUERY PLAN`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryPlan {
+    /// Top-level steps in the order SQLite reported them
+    pub nodes: Vec<PlanNode>,
+}
+
+/// One step of a query plan, such as `SEARCH users USING INDEX idx_email (email=?)`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlanNode {
+    /// SQLite's description of the step
+    pub detail: String,
+    /// True when the step reads every row of a table without an index
+    pub full_scan: bool,
+    /// Steps nested under this one, like the loops of a subquery
+    pub children: Vec<PlanNode>,
+}
+
+impl QueryPlan {
+    /// Build the tree from `(id, parent, detail)` rows
+    pub(crate) fn from_rows(rows: Vec<(i64, i64, String)>) -> Self {
+        QueryPlan { nodes: children_of(0, &rows) }
+    }
+
+    /// Steps that scan a whole table, anywhere in the tree
+    pub fn full_scans(&self) -> Vec<&PlanNode> {
+        let mut found = Vec::new();
+        let mut pending: Vec<&PlanNode> = self.nodes.iter().rev().collect();
+        while let Some(node) = pending.pop() {
+            if node.full_scan {
+                found.push(node);
+            }
+            pending.extend(node.children.iter().rev());
+        }
+        found
+    }
+
+    /// True when any step scans a whole table
+    pub fn has_full_scan(&self) -> bool {
+        !self.full_scans().is_empty()
+    }
+}
+
+fn children_of(parent: i64, rows: &[(i64, i64, String)]) -> Vec<PlanNode> {
+    rows.iter()
+        .filter(|(id, row_parent, _)| *row_parent == parent && *id != parent)
+        .map(|(id, _, detail)| PlanNode {
+            detail: detail.clone(),
+            full_scan: is_full_scan(detail),
+            children: children_of(*id, rows),
+        })
+        .collect()
+}
+
+/// `SCAN users` (or `SCAN TABLE users` before SQLite 3.36) without an index
+fn is_full_scan(detail: &str) -> bool {
+    detail.starts_with("SCAN ")
+        && !detail.contains(" USING ")
+        && detail != "SCAN CONSTANT ROW"
+        && !detail.starts_with("SCAN SUBQUERY")
+}
+
+/// Renders the tree the way the `sqlite3` shell's `.eqp` does
+impl fmt::Display for QueryPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "QUERY PLAN")?;
+        write_nodes(f, &self.nodes, "")
+    }
+}
+
+fn write_nodes(f: &mut fmt::Formatter<'_>, nodes: &[PlanNode], indent: &str) -> fmt::Result {
+    for (i, node) in nodes.iter().enumerate() {
+        let last = i + 1 == nodes.len();
+        writeln!(f, "{}{}{}", indent, if last { "`--" } else { "|--" }, node.detail)?;
+        let nested = format!("{}{}", indent, if last { "   " } else { "|  " });
+        write_nodes(f, &node.children, &nested)?;
+    }
+    Ok(())
+}
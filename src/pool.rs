@@ -0,0 +1,298 @@
+use crate::datetime::{Clock, SystemClock};
+use crate::db::Database;
+use crate::logging::{QueryLogger, Tracer};
+use crate::options::DatabaseOptions;
+use crate::util::DeserializeMode;
+use rusqlite::{Connection, Result as SqliteResult};
+use std::ops::{Deref, DerefMut};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+type InitHook = dyn Fn(&Connection) -> SqliteResult<()> + Send + Sync;
+
+/// A thread-safe pool of `Database` handles for one SQLite file
+///
+/// Cloning a pool is cheap and every clone shares the same connections.
+/// Each connection is a separate SQLite connection, so pools need a database
+/// file; `":memory:"` would give every connection its own empty database.
+#[derive(Clone)]
+pub struct Pool {
+    inner: Arc<PoolInner>,
+}
+
+struct PoolInner {
+    path: PathBuf,
+    options: DatabaseOptions,
+    reader_options: DatabaseOptions,
+    init: Option<Arc<InitHook>>,
+    clock: Arc<dyn Clock>,
+    deserialize_mode: DeserializeMode,
+    tracer: Tracer,
+    checkout_timeout: Duration,
+    /// Read connections, or all connections when there is no dedicated writer
+    readers: Arc<Slots>,
+    /// The single writer connection in single-writer mode
+    writer: Option<Arc<Slots>>,
+}
+
+/// Builder for a `Pool`
+pub struct PoolBuilder {
+    path: PathBuf,
+    options: DatabaseOptions,
+    init: Option<Arc<InitHook>>,
+    clock: Arc<dyn Clock>,
+    deserialize_mode: DeserializeMode,
+    tracer: Tracer,
+    max_size: usize,
+    checkout_timeout: Duration,
+    single_writer: bool,
+}
+
+impl Pool {
+    /// Start configuring a pool for the database file at `path`
+    pub fn builder<P: AsRef<Path>>(path: P) -> PoolBuilder {
+        PoolBuilder {
+            path: path.as_ref().to_path_buf(),
+            options: DatabaseOptions::default(),
+            init: None,
+            clock: Arc::new(SystemClock),
+            deserialize_mode: DeserializeMode::Loose,
+            tracer: Tracer::default(),
+            max_size: 4,
+            checkout_timeout: Duration::from_secs(30),
+            single_writer: false,
+        }
+    }
+
+    /// Check out a connection, waiting up to the checkout timeout for one to free up
+    /// In single-writer mode this is a read-only connection; use `writer` for writes
+    pub fn get(&self) -> SqliteResult<PooledDatabase> {
+        self.checkout(&self.inner.readers, &self.inner.reader_options)
+    }
+
+    /// Check out the connection used for writes
+    /// In single-writer mode all writers share one connection and take turns;
+    /// otherwise this is the same as `get`
+    pub fn writer(&self) -> SqliteResult<PooledDatabase> {
+        match &self.inner.writer {
+            Some(writer) => self.checkout(writer, &self.inner.options),
+            None => self.get(),
+        }
+    }
+
+    /// Number of connections currently open, idle or checked out
+    pub fn size(&self) -> usize {
+        let writers = self.inner.writer.as_ref().map_or(0, |writer| writer.lock().open);
+        self.inner.readers.lock().open + writers
+    }
+
+    /// Number of open connections waiting to be checked out
+    pub fn idle(&self) -> usize {
+        let writers = self.inner.writer.as_ref().map_or(0, |writer| writer.lock().idle.len());
+        self.inner.readers.lock().idle.len() + writers
+    }
+
+    fn checkout(&self, slots: &Arc<Slots>, options: &DatabaseOptions) -> SqliteResult<PooledDatabase> {
+        let deadline = Instant::now() + self.inner.checkout_timeout;
+        let mut state = slots.lock();
+        loop {
+            if let Some(db) = state.idle.pop() {
+                return Ok(PooledDatabase { db: Some(db), slots: Arc::clone(slots) });
+            }
+
+            if state.open < slots.max_size {
+                // Open outside the lock so other threads can return connections meanwhile
+                state.open += 1;
+                drop(state);
+                return match self.inner.connect(options) {
+                    Ok(db) => Ok(PooledDatabase { db: Some(db), slots: Arc::clone(slots) }),
+                    Err(e) => {
+                        slots.lock().open -= 1;
+                        slots.available.notify_one();
+                        Err(e)
+                    }
+                };
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(rusqlite::Error::SqliteFailure(
+                    rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_BUSY),
+                    Some("timed out waiting for a pooled connection".to_string()),
+                ));
+            }
+            state = slots.available
+                .wait_timeout(state, deadline - now)
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+        }
+    }
+}
+
+impl PoolInner {
+    fn connect(&self, options: &DatabaseOptions) -> SqliteResult<Database> {
+        let conn = options.open(&self.path)?;
+        if let Some(init) = &self.init {
+            init(&conn)?;
+        }
+
+        let mut db = Database::from_connection(conn).with_options(options);
+        db.clock = Arc::clone(&self.clock);
+        db.deserialize_mode = self.deserialize_mode;
+        db.tracer = self.tracer.clone();
+        Ok(db)
+    }
+}
+
+impl PoolBuilder {
+    /// Options applied to every connection when it is opened
+    pub fn options(mut self, options: DatabaseOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Run `init` on every new connection after the options are applied
+    pub fn on_connect<F>(mut self, init: F) -> Self
+    where F: Fn(&Connection) -> SqliteResult<()> + Send + Sync + 'static {
+        self.init = Some(Arc::new(init));
+        self
+    }
+
+    /// Use a custom clock for `created_at`/`updated_at` management on every connection
+    pub fn clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Choose how loaded rows are matched against model field types on every connection
+    pub fn deserialize_mode(mut self, mode: DeserializeMode) -> Self {
+        self.deserialize_mode = mode;
+        self
+    }
+
+    /// Report every statement run on any pooled connection
+    pub fn query_logger(mut self, logger: impl QueryLogger + 'static) -> Self {
+        self.tracer.logger = Some(Arc::new(logger));
+        self
+    }
+
+    /// Log values bound to these fields as `'[REDACTED]'` on every connection
+    pub fn redacted_fields(mut self, fields: &[&str]) -> Self {
+        self.tracer.redacted = Arc::new(fields.iter().map(|f| f.to_string()).collect());
+        self
+    }
+
+    /// Maximum number of connections handed out by `get` (defaults to 4)
+    pub fn max_size(mut self, max_size: usize) -> Self {
+        self.max_size = max_size.max(1);
+        self
+    }
+
+    /// How long `get` and `writer` wait for a free connection before failing (defaults to 30s)
+    pub fn checkout_timeout(mut self, timeout: Duration) -> Self {
+        self.checkout_timeout = timeout;
+        self
+    }
+
+    /// Route writes through one dedicated connection and open the others read-only
+    ///
+    /// SQLite only allows one writer at a time, so this avoids SQLITE_BUSY between
+    /// pooled writers. Pair it with `JournalMode::Wal` so readers are not blocked
+    /// while the writer works.
+    pub fn single_writer(mut self, single_writer: bool) -> Self {
+        self.single_writer = single_writer;
+        self
+    }
+
+    /// Create the pool, opening one connection up front so bad paths and options fail early
+    pub fn build(self) -> SqliteResult<Pool> {
+        let reader_options = if self.single_writer {
+            self.options.for_readers()
+        } else {
+            self.options.clone()
+        };
+
+        let pool = Pool {
+            inner: Arc::new(PoolInner {
+                path: self.path,
+                options: self.options,
+                reader_options,
+                init: self.init,
+                clock: self.clock,
+                deserialize_mode: self.deserialize_mode,
+                tracer: self.tracer,
+                checkout_timeout: self.checkout_timeout,
+                readers: Arc::new(Slots::new(self.max_size)),
+                writer: self.single_writer.then(|| Arc::new(Slots::new(1))),
+            }),
+        };
+
+        // The writer goes first: it creates the file and sets the journal mode
+        drop(pool.writer()?);
+        Ok(pool)
+    }
+}
+
+struct Slots {
+    state: Mutex<SlotState>,
+    available: Condvar,
+    max_size: usize,
+}
+
+struct SlotState {
+    idle: Vec<Database>,
+    open: usize,
+}
+
+impl Slots {
+    fn new(max_size: usize) -> Self {
+        Slots {
+            state: Mutex::new(SlotState { idle: Vec::new(), open: 0 }),
+            available: Condvar::new(),
+            max_size,
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, SlotState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// A `Database` checked out of a `Pool`, returned to it when dropped
+pub struct PooledDatabase {
+    db: Option<Database>,
+    slots: Arc<Slots>,
+}
+
+impl Deref for PooledDatabase {
+    type Target = Database;
+
+    fn deref(&self) -> &Database {
+        self.db.as_ref().expect("pooled database used after release")
+    }
+}
+
+impl DerefMut for PooledDatabase {
+    fn deref_mut(&mut self) -> &mut Database {
+        self.db.as_mut().expect("pooled database used after release")
+    }
+}
+
+impl Drop for PooledDatabase {
+    fn drop(&mut self) {
+        if let Some(db) = self.db.take() {
+            db.set_actor(None);
+            // A connection whose transaction cannot be rolled back is closed instead of reused
+            let reusable = db.rollback_open_transaction().is_ok();
+            let mut state = self.slots.lock();
+            if reusable {
+                state.idle.push(db);
+            } else {
+                state.open -= 1;
+            }
+            drop(state);
+            self.slots.available.notify_one();
+        }
+    }
+}
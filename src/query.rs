@@ -1,16 +1,57 @@
-use crate::model::Model;
-use rusqlite::{Connection, Result as SqliteResult, Row, params_from_iter};
-use serde_json;
+use crate::audit::{Action, Auditor, Target};
+use crate::datetime::{iso8601, timestamp_value, Clock, SystemClock, Timestamp};
+use crate::logging::Tracer;
+use crate::model::{ColumnType, Model};
+use crate::plan::QueryPlan;
+use crate::search::SearchHit;
+use crate::statement_cache::{prepare, StatementCache};
+use crate::stream::RowStream;
+use crate::util::{quote_ident, row_to_model, to_sql_value, DeserializeMode};
+use rusqlite::{Connection, Result as SqliteResult, params_from_iter};
+use rusqlite::types::Value as SqlValue;
+use std::sync::Arc;
 
-/// Simple query builder for SELECT statements
+/// Which soft-deleted rows a query sees
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Trashed {
+    Exclude,
+    Include,
+    Only,
+}
+
+/// Simple query builder for SELECT and bulk UPDATE statements
+///
+/// Column names are quoted and checked against the model's `fields()`; an unknown
+/// column makes the query fail with `rusqlite::Error::InvalidColumnName` when it runs.
 pub struct QueryBuilder<'a> {
     conn: &'a Connection,
+    pub(crate) clock: Arc<dyn Clock>,
+    pub(crate) deserialize_mode: DeserializeMode,
+    pub(crate) statements: Option<&'a StatementCache>,
+    pub(crate) tracer: Tracer,
+    pub(crate) actor: Option<String>,
     table_name: String,
     fields: Vec<String>,
+    primary_key: &'static str,
+    search_fields: &'static [&'static str],
+    column_type: fn(&str) -> ColumnType,
+    deleted_at_field: Option<String>,
+    trashed: Trashed,
     where_clauses: Vec<String>,
-    where_values: Vec<String>,
+    /// Bound values, tagged with the field they are compared against for logging
+    where_values: Vec<(Option<String>, SqlValue)>,
+    assignments: Vec<(String, SqlValue)>,
     order_by: Option<String>,
     limit: Option<usize>,
+    /// Full-text query matched against the model's FTS5 table
+    search: Option<String>,
+    /// `highlight`/`snippet` calls returned with each search hit, and their bound arguments
+    fragments: Vec<String>,
+    fragment_values: Vec<SqlValue>,
+    /// First column name passed in that the model does not declare, reported when the query runs
+    invalid_column: Option<String>,
+    /// Set when a `where_time_*` value cannot be stored in its column, reported when the query runs
+    invalid_timestamp: bool,
 }
 
 impl<'a> QueryBuilder<'a> {
@@ -21,47 +62,229 @@ impl<'a> QueryBuilder<'a> {
         
         QueryBuilder {
             conn,
+            clock: Arc::new(SystemClock),
+            deserialize_mode: DeserializeMode::Loose,
+            statements: None,
+            tracer: Tracer::default(),
+            actor: None,
             table_name,
             fields,
+            primary_key: T::primary_key(),
+            search_fields: T::search_fields(),
+            column_type: T::column_type,
+            deleted_at_field: T::deleted_at_field().map(|s| s.to_string()),
+            trashed: Trashed::Exclude,
             where_clauses: Vec::new(),
             where_values: Vec::new(),
+            assignments: Vec::new(),
             order_by: None,
             limit: None,
+            search: None,
+            fragments: Vec::new(),
+            fragment_values: Vec::new(),
+            invalid_column: None,
+            invalid_timestamp: false,
         }
     }
 
     /// Add a WHERE clause
     pub fn where_eq(mut self, field: &str, value: impl ToString) -> Self {
-        self.where_clauses.push(format!("{} = ?", field));
-        self.where_values.push(value.to_string());
+        let value = self.bind_value(field, value.to_string());
+        let column = self.column(field);
+        self.where_clauses.push(format!("{} = ?", column));
+        self.where_values.push((Some(field.to_string()), value));
         self
     }
 
     /// Add a WHERE LIKE clause
     pub fn where_like(mut self, field: &str, pattern: impl ToString) -> Self {
-        self.where_clauses.push(format!("{} LIKE ?", field));
-        self.where_values.push(pattern.to_string());
+        let column = self.column(field);
+        self.where_clauses.push(format!("{} LIKE ?", column));
+        self.where_values.push((Some(field.to_string()), SqlValue::Text(pattern.to_string())));
         self
     }
 
     /// Add a WHERE > clause
+    /// Compare timestamps with `where_time_gt`, which formats them the way they are stored
     pub fn where_gt(mut self, field: &str, value: impl ToString) -> Self {
-        self.where_clauses.push(format!("{} > ?", field));
-        self.where_values.push(value.to_string());
+        let value = self.bind_value(field, value.to_string());
+        let column = self.column(field);
+        self.where_clauses.push(format!("{} > ?", column));
+        self.where_values.push((Some(field.to_string()), value));
         self
     }
 
     /// Add a WHERE < clause
+    /// Compare timestamps with `where_time_lt`, which formats them the way they are stored
     pub fn where_lt(mut self, field: &str, value: impl ToString) -> Self {
-        self.where_clauses.push(format!("{} < ?", field));
-        self.where_values.push(value.to_string());
+        let value = self.bind_value(field, value.to_string());
+        let column = self.column(field);
+        self.where_clauses.push(format!("{} < ?", column));
+        self.where_values.push((Some(field.to_string()), value));
+        self
+    }
+
+    /// Add a WHERE clause comparing a value inside a JSON column
+    /// `path` uses SQLite JSON path syntax, e.g. `$.theme` or `$.tags[0]`
+    pub fn where_json_eq(self, field: &str, path: &str, value: impl Into<serde_json::Value>) -> Self {
+        self.where_json(field, "=", path, value.into())
+    }
+
+    /// Add a WHERE > clause on a value inside a JSON column
+    pub fn where_json_gt(self, field: &str, path: &str, value: impl Into<serde_json::Value>) -> Self {
+        self.where_json(field, ">", path, value.into())
+    }
+
+    /// Add a WHERE < clause on a value inside a JSON column
+    pub fn where_json_lt(self, field: &str, path: &str, value: impl Into<serde_json::Value>) -> Self {
+        self.where_json(field, "<", path, value.into())
+    }
+
+    /// Add a WHERE clause matching a timestamp column
+    /// INTEGER columns are compared as unix seconds, others as canonical ISO-8601 text
+    pub fn where_time_eq<T: Timestamp>(self, field: &str, value: T) -> Self {
+        self.where_time(field, "=", value)
+    }
+
+    /// Add a WHERE > clause on a timestamp column, matching values later than `value`
+    pub fn where_time_gt<T: Timestamp>(self, field: &str, value: T) -> Self {
+        self.where_time(field, ">", value)
+    }
+
+    /// Add a WHERE < clause on a timestamp column, matching values earlier than `value`
+    pub fn where_time_lt<T: Timestamp>(self, field: &str, value: T) -> Self {
+        self.where_time(field, "<", value)
+    }
+
+    fn where_time<T: Timestamp>(mut self, field: &str, op: &str, value: T) -> Self {
+        let value = match timestamp_value(&value, (self.column_type)(field)) {
+            Ok(value) => value,
+            Err(_) => {
+                self.invalid_timestamp = true;
+                SqlValue::Null
+            }
+        };
+        let column = self.column(field);
+        self.where_clauses.push(format!("{} {} ?", column, op));
+        self.where_values.push((Some(field.to_string()), value));
+        self
+    }
+
+    /// Quote a column name, remembering it if the model does not declare it
+    fn column(&mut self, field: &str) -> String {
+        if self.invalid_column.is_none() && !self.fields.iter().any(|f| f == field) {
+            self.invalid_column = Some(field.to_string());
+        }
+        quote_ident(field)
+    }
+
+    /// Fail with `InvalidColumnName` if any method was given an unknown column, or with
+    /// `ToSqlConversionFailure` if a `where_time_*` value is outside the storable years
+    fn check_columns(&self) -> SqliteResult<()> {
+        match &self.invalid_column {
+            Some(field) => Err(rusqlite::Error::InvalidColumnName(field.clone())),
+            None if self.invalid_timestamp => Err(rusqlite::Error::ToSqlConversionFailure(iso8601::OUT_OF_RANGE.into())),
+            None => Ok(()),
+        }
+    }
+
+    /// Values are bound like stored values: decimals scaled, integer enum variant names
+    /// mapped to their discriminants and `true`/`false` to 1/0 in numeric columns;
+    /// everything else is bound as text
+    fn bind_value(&self, field: &str, value: String) -> SqlValue {
+        match (self.column_type)(field) {
+            ColumnType::Integer | ColumnType::Real if value == "true" || value == "false" => {
+                SqlValue::Integer((value == "true") as i64)
+            }
+            ColumnType::Decimal(scale) => match crate::decimal::to_scaled(&value, scale) {
+                Some(scaled) => SqlValue::Integer(scaled),
+                None => SqlValue::Text(value),
+            },
+            ColumnType::IntegerEnum(variants) => match variants.iter().find(|(name, _)| *name == value) {
+                Some((_, discriminant)) => SqlValue::Integer(*discriminant),
+                None => SqlValue::Text(value),
+            },
+            _ => SqlValue::Text(value),
+        }
+    }
+
+    fn where_json(mut self, field: &str, op: &str, path: &str, value: serde_json::Value) -> Self {
+        // json_extract returns SQL values, so bind with matching types (true/false are 1/0)
+        let value = match value {
+            serde_json::Value::Bool(b) => SqlValue::Integer(b as i64),
+            serde_json::Value::Array(_) | serde_json::Value::Object(_) => SqlValue::Text(value.to_string()),
+            _ => to_sql_value(&value).unwrap_or(SqlValue::Null),
+        };
+
+        let column = self.column(field);
+        self.where_clauses.push(format!("json_extract({}, ?) {} ?", column, op));
+        self.where_values.push((None, SqlValue::Text(path.to_string())));
+        self.where_values.push((Some(field.to_string()), value));
+        self
+    }
+
+    /// Only match rows whose `search_fields` match an FTS5 full-text query, best matches first
+    /// The query uses FTS5 syntax, e.g. `rust AND (orm OR sqlite)` or `"exact phrase"`;
+    /// an explicit `order_by` replaces the ranking
+    pub fn search(mut self, query: impl ToString) -> Self {
+        self.search = Some(query.to_string());
+        self
+    }
+
+    /// Return the whole text of a search field with matched terms wrapped in `open` and `close`
+    /// Read it from `SearchHit::fragments` via `fetch_hits`
+    pub fn highlight(mut self, field: &str, open: &str, close: &str) -> Self {
+        let column = self.search_column(field);
+        self.fragments.push(format!("highlight({}, {}, ?, ?)", self.fts_table(), column));
+        self.fragment_values.extend([SqlValue::Text(open.to_string()), SqlValue::Text(close.to_string())]);
         self
     }
 
+    /// Return a fragment of up to `max_tokens` tokens (at most 64) from a search field around
+    /// the matched terms, wrapped like `highlight`, with `ellipsis` marking cut text
+    pub fn snippet(mut self, field: &str, open: &str, close: &str, ellipsis: &str, max_tokens: usize) -> Self {
+        let column = self.search_column(field);
+        self.fragments.push(format!("snippet({}, {}, ?, ?, ?, ?)", self.fts_table(), column));
+        self.fragment_values.extend([
+            SqlValue::Text(open.to_string()),
+            SqlValue::Text(close.to_string()),
+            SqlValue::Text(ellipsis.to_string()),
+            SqlValue::Integer(max_tokens.min(64) as i64),
+        ]);
+        self
+    }
+
+    /// Position of a field in the FTS5 table, remembering it if it is not a search field
+    fn search_column(&mut self, field: &str) -> usize {
+        match self.search_fields.iter().position(|f| *f == field) {
+            Some(idx) => idx,
+            None => {
+                if self.invalid_column.is_none() {
+                    self.invalid_column = Some(field.to_string());
+                }
+                0
+            }
+        }
+    }
+
+    fn fts_table(&self) -> String {
+        quote_ident(&crate::search::fts_table(&self.table_name))
+    }
+
+    /// Searching needs a model with search fields, and fragments need a search
+    fn check_search(&self) -> SqliteResult<()> {
+        let searching = self.search.is_some() || !self.fragments.is_empty();
+        if searching && (self.search.is_none() || self.search_fields.is_empty()) {
+            return Err(rusqlite::Error::InvalidQuery);
+        }
+        Ok(())
+    }
+
     /// Add ORDER BY clause
     pub fn order_by(mut self, field: &str, ascending: bool) -> Self {
         let direction = if ascending { "ASC" } else { "DESC" };
-        self.order_by = Some(format!("{} {}", field, direction));
+        let column = self.column(field);
+        self.order_by = Some(format!("{} {}", column, direction));
         self
     }
 
@@ -71,41 +294,229 @@ impl<'a> QueryBuilder<'a> {
         self
     }
 
+    /// Choose how rows from this query are matched against model field types
+    pub fn deserialize_mode(mut self, mode: DeserializeMode) -> Self {
+        self.deserialize_mode = mode;
+        self
+    }
+
+    /// Include soft-deleted rows
+    pub fn with_trashed(mut self) -> Self {
+        self.trashed = Trashed::Include;
+        self
+    }
+
+    /// Only return soft-deleted rows
+    pub fn only_trashed(mut self) -> Self {
+        self.trashed = Trashed::Only;
+        self
+    }
+
+    /// Build the WHERE clause, including the soft delete filter
+    fn where_sql(&self) -> String {
+        let mut clauses = self.where_clauses.clone();
+
+        if let Some(field) = &self.deleted_at_field {
+            match self.trashed {
+                Trashed::Exclude => clauses.push(format!("{} IS NULL", quote_ident(field))),
+                Trashed::Only => clauses.push(format!("{} IS NOT NULL", quote_ident(field))),
+                Trashed::Include => {}
+            }
+        }
+
+        if clauses.is_empty() {
+            String::new()
+        } else {
+            format!(" WHERE {}", clauses.join(" AND "))
+        }
+    }
+
+    /// Add a SET assignment used by `update`
+    pub fn set(mut self, field: &str, value: impl ToString) -> Self {
+        let value = self.bind_value(field, value.to_string());
+        self.column(field);
+        self.assignments.push((field.to_string(), value));
+        self
+    }
+
+    /// Update every row matching the WHERE clauses and return the number of rows changed
+    /// ORDER BY and LIMIT are ignored; versioned models have their version incremented
+    pub fn update<T: Model>(self) -> SqliteResult<usize> {
+        self.check_columns()?;
+        self.check_search()?;
+        let mut set_clauses = Vec::new();
+        let mut values = Vec::new();
+
+        for (field, value) in &self.assignments {
+            set_clauses.push(format!("{} = ?", quote_ident(field)));
+            values.push((Some(field.clone()), value.clone()));
+        }
+
+        if let Some(field) = T::updated_at_field() {
+            set_clauses.push(format!("{} = ?", quote_ident(field)));
+            values.push((Some(field.to_string()), timestamp_value(&self.clock.now(), T::column_type(field))?));
+        }
+
+        if set_clauses.is_empty() {
+            return Err(rusqlite::Error::InvalidQuery);
+        }
+
+        // Loaded copies of versioned rows become stale
+        if let Some(field) = T::version_field() {
+            set_clauses.push(format!("{0} = {0} + 1", quote_ident(field)));
+        }
+
+        let mut where_sql = self.where_sql();
+        let mut where_values = self.where_values;
+        if let Some(query) = self.search {
+            where_sql.push_str(if where_sql.is_empty() { " WHERE " } else { " AND " });
+            where_sql.push_str(&format!(
+                "{} IN (SELECT rowid FROM {1} WHERE {1} MATCH ?)",
+                quote_ident(self.primary_key),
+                quote_ident(&crate::search::fts_table(&self.table_name))
+            ));
+            where_values.push((None, SqlValue::Text(query)));
+        }
+
+        let sql = format!(
+            "UPDATE {} SET {}{}",
+            quote_ident(&self.table_name),
+            set_clauses.join(", "),
+            where_sql
+        );
+
+        let target = Target::Where(where_sql, where_values.clone());
+        values.extend(where_values);
+        let write = || {
+            let started = self.tracer.start();
+            let changed = prepare(self.conn, self.statements, &sql)?
+                .execute(params_from_iter(values.iter().map(|(_, value)| value)))?;
+            self.tracer.finish(started, &sql, values.iter().map(|(field, value)| (field.as_deref(), value)), changed);
+            Ok(changed)
+        };
+
+        match T::history_table() {
+            Some(history) => {
+                let auditor = Auditor {
+                    conn: self.conn,
+                    statements: self.statements,
+                    tracer: &self.tracer,
+                    actor: self.actor.clone(),
+                    now: self.clock.now(),
+                };
+                auditor.record::<T>(history, Action::Update, target, write)
+            }
+            None => write(),
+        }
+    }
+
     /// Execute the query and return results
     pub fn fetch<T: Model>(self) -> SqliteResult<Vec<T>> {
-        let mut sql = format!(
-            "SELECT {} FROM {}",
-            self.fields.join(", "),
-            self.table_name
-        );
+        self.fetch_iter::<T>()?.iter()?.collect()
+    }
+
+    /// Prepare the query for lazy iteration instead of collecting every row
+    pub fn fetch_iter<T: Model>(self) -> SqliteResult<RowStream<'a, T>> {
+        let sql = self.select_sql()?;
+        let stmt = prepare(self.conn, self.statements, &sql)?;
+        Ok(RowStream::new(stmt, sql, self.select_values(), self.deserialize_mode, self.tracer))
+    }
+
+    /// Run a `search` and return each match with its rank and requested highlights/snippets
+    pub fn fetch_hits<T: Model>(self) -> SqliteResult<Vec<SearchHit<T>>> {
+        if self.search.is_none() {
+            return Err(rusqlite::Error::InvalidQuery);
+        }
+        let sql = self.select_sql()?;
+        let values = self.select_values();
+        let rank = self.fields.len();
+        let mut stmt = prepare(self.conn, self.statements, &sql)?;
+
+        let started = self.tracer.start();
+        let mut rows = stmt.query(params_from_iter(values.iter().map(|(_, value)| value)))?;
+        let mut hits = Vec::new();
+        while let Some(row) = rows.next()? {
+            let fragments = (0..self.fragments.len())
+                .map(|i| Ok(row.get::<_, Option<String>>(rank + 1 + i)?.unwrap_or_default()))
+                .collect::<SqliteResult<Vec<_>>>()?;
+            hits.push(SearchHit { model: row_to_model(row, self.deserialize_mode)?, rank: row.get(rank)?, fragments });
+        }
+        self.tracer.finish(started, &sql, values.iter().map(|(field, value)| (field.as_deref(), value)), hits.len());
+        Ok(hits)
+    }
+
+    /// The SELECT statement `fetch` would run and its bound parameters, without executing it
+    pub fn to_sql(&self) -> SqliteResult<(String, Vec<SqlValue>)> {
+        let sql = self.select_sql()?;
+        Ok((sql, self.select_values().into_iter().map(|(_, value)| value).collect()))
+    }
+
+    /// Ask SQLite how it would run this query with `EXPLAIN QUERY PLAN`
+    /// Use `QueryPlan::full_scans` to check whether the query can use an index
+    pub fn explain(&self) -> SqliteResult<QueryPlan> {
+        let sql = format!("EXPLAIN QUERY PLAN {}", self.select_sql()?);
+        let started = self.tracer.start();
+        let mut stmt = self.conn.prepare(&sql)?;
+        let values = self.select_values();
+        let rows = stmt.query_map(params_from_iter(values.iter().map(|(_, value)| value)), |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?, row.get::<_, String>(3)?))
+        })?;
+        let rows = rows.collect::<SqliteResult<Vec<_>>>()?;
+        self.tracer.finish(started, &sql, values.iter().map(|(field, value)| (field.as_deref(), value)), rows.len());
+        Ok(QueryPlan::from_rows(rows))
+    }
+
+    /// Build the SELECT statement for `fetch`
+    fn select_sql(&self) -> SqliteResult<String> {
+        self.check_columns()?;
+        self.check_search()?;
+        let mut columns: Vec<String> = self.fields.iter().map(|field| quote_ident(field)).collect();
+        let mut from = quote_ident(&self.table_name);
 
-        if !self.where_clauses.is_empty() {
-            sql.push_str(" WHERE ");
-            sql.push_str(&self.where_clauses.join(" AND "));
+        // Matches come from a subquery on the FTS5 table whose columns cannot clash with the model's
+        if self.search.is_some() {
+            let fts = self.fts_table();
+            let mut matched = vec!["rowid AS \"pebble_rowid\"".to_string(), "rank AS \"pebble_rank\"".to_string()];
+            columns.push("\"pebble_search\".\"pebble_rank\"".to_string());
+            for (i, fragment) in self.fragments.iter().enumerate() {
+                matched.push(format!("{} AS \"pebble_fragment_{}\"", fragment, i));
+                columns.push(format!("\"pebble_search\".\"pebble_fragment_{}\"", i));
+            }
+            from = format!(
+                "{0} JOIN (SELECT {1} FROM {2} WHERE {2} MATCH ?) AS \"pebble_search\" ON \"pebble_search\".\"pebble_rowid\" = {0}.{3}",
+                from,
+                matched.join(", "),
+                fts,
+                quote_ident(self.primary_key)
+            );
         }
 
-        if let Some(order) = self.order_by {
+        let mut sql = format!("SELECT {} FROM {}", columns.join(", "), from);
+        sql.push_str(&self.where_sql());
+
+        if let Some(order) = &self.order_by {
             sql.push_str(" ORDER BY ");
-            sql.push_str(&order);
+            sql.push_str(order);
+        } else if self.search.is_some() {
+            sql.push_str(" ORDER BY \"pebble_search\".\"pebble_rank\"");
         }
 
         if let Some(limit) = self.limit {
             sql.push_str(&format!(" LIMIT {}", limit));
         }
 
-        let mut stmt = self.conn.prepare(&sql)?;
-        let fields_refs: Vec<&str> = self.fields.iter().map(|s| s.as_str()).collect();
-        
-        let rows = stmt.query_map(params_from_iter(self.where_values.iter()), |row| {
-            row_to_model::<T>(row, &fields_refs)
-        })?;
+        Ok(sql)
+    }
 
-        let mut results = Vec::new();
-        for row_result in rows {
-            results.push(row_result?);
+    /// Bound values of the SELECT statement in the order they appear: search arguments, then WHERE values
+    fn select_values(&self) -> Vec<(Option<String>, SqlValue)> {
+        let mut values: Vec<(Option<String>, SqlValue)> = Vec::new();
+        if let Some(query) = &self.search {
+            values.extend(self.fragment_values.iter().map(|value| (None, value.clone())));
+            values.push((None, SqlValue::Text(query.clone())));
         }
-
-        Ok(results)
+        values.extend(self.where_values.iter().cloned());
+        values
     }
 
     /// Execute the query and return the first result
@@ -114,9 +525,3 @@ impl<'a> QueryBuilder<'a> {
         Ok(results.into_iter().next())
     }
 }
-
-/// Helper function to convert a Row to a Model instance
-fn row_to_model<T: Model>(row: &Row, fields: &[&str]) -> SqliteResult<T> {
-    crate::util::row_to_model(row, fields)
-}
-
@@ -0,0 +1,70 @@
+use crate::model::Model;
+use crate::util::{check_field, quote_ident};
+use rusqlite::Result as SqliteResult;
+
+/// A model found by `QueryBuilder::search`
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchHit<T> {
+    pub model: T,
+    /// FTS5 bm25 score; lower (more negative) is a better match
+    pub rank: f64,
+    /// Highlighted text and snippets, in the order `highlight` and `snippet` were called
+    pub fragments: Vec<String>,
+}
+
+/// Name of the FTS5 table indexing a model's `search_fields`
+pub(crate) fn fts_table(table: &str) -> String {
+    format!("{}_fts", table)
+}
+
+/// Statements creating the external-content FTS5 table and the triggers keeping it in sync
+pub(crate) fn search_ddl<T: Model>() -> SqliteResult<Vec<String>> {
+    let search_fields = T::search_fields();
+    for field in search_fields {
+        check_field::<T>(field)?;
+    }
+
+    let table = quote_ident(T::table_name());
+    let fts_name = fts_table(T::table_name());
+    let fts = quote_ident(&fts_name);
+    let pk = quote_ident(T::primary_key());
+    let columns: Vec<String> = search_fields.iter().map(|field| quote_ident(field)).collect();
+    let prefixed = |prefix: &str| -> String {
+        columns.iter().map(|column| format!("{}.{}", prefix, column)).collect::<Vec<_>>().join(", ")
+    };
+
+    // An FTS5 external-content delete must repeat the indexed values being removed
+    let insert = format!(
+        "INSERT INTO {fts} (rowid, {}) VALUES (new.{pk}, {});",
+        columns.join(", "),
+        prefixed("new")
+    );
+    let delete = format!(
+        "INSERT INTO {fts} ({fts}, rowid, {}) VALUES ('delete', old.{pk}, {});",
+        columns.join(", "),
+        prefixed("old")
+    );
+    let trigger = |suffix: &str| quote_ident(&format!("{}_{}", fts_name, suffix));
+
+    Ok(vec![
+        format!(
+            "CREATE VIRTUAL TABLE IF NOT EXISTS {fts} USING fts5({}, content='{}', content_rowid='{}')",
+            columns.join(", "),
+            T::table_name().replace('\'', "''"),
+            T::primary_key().replace('\'', "''")
+        ),
+        format!("CREATE TRIGGER IF NOT EXISTS {} AFTER INSERT ON {table} BEGIN {insert} END", trigger("insert")),
+        format!("CREATE TRIGGER IF NOT EXISTS {} AFTER DELETE ON {table} BEGIN {delete} END", trigger("delete")),
+        format!(
+            "CREATE TRIGGER IF NOT EXISTS {} AFTER UPDATE OF {pk}, {} ON {table} BEGIN {delete} {insert} END",
+            trigger("update"),
+            columns.join(", ")
+        ),
+    ])
+}
+
+/// Statement re-indexing every existing row, for tables that gain a search index
+pub(crate) fn rebuild_sql(table: &str) -> String {
+    let fts = quote_ident(&fts_table(table));
+    format!("INSERT INTO {fts} ({fts}) VALUES ('rebuild')")
+}
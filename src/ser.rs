@@ -0,0 +1,453 @@
+use crate::model::{ColumnType, Model};
+use rusqlite::types::Value as SqlValue;
+use rusqlite::Result as SqliteResult;
+use serde::ser::{self, Impossible, Serialize, Serializer};
+use std::fmt::Display;
+
+/// Serialize a model straight into SQLite values, one per entry of `T::fields()`
+///
+/// Each field is converted according to its declared column type, so integers
+/// stay integers, floats keep their exact bits in REAL columns and byte buffers
+/// become BLOBs without going through an intermediate JSON document.
+pub fn model_values<T: Model>(model: &T) -> SqliteResult<Vec<SqlValue>> {
+    let mut columns = model
+        .serialize(ModelSerializer { column_type: T::column_type })
+        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+
+    T::fields()
+        .iter()
+        .map(|field| {
+            columns
+                .iter_mut()
+                .find(|(name, _)| name == field)
+                .map(|(_, value)| std::mem::replace(value, SqlValue::Null))
+                .ok_or(rusqlite::Error::InvalidQuery)
+        })
+        .collect()
+}
+
+/// Error raised while binding a model's fields
+#[derive(Debug)]
+pub struct SerError(String);
+
+impl Display for SerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for SerError {}
+
+impl ser::Error for SerError {
+    fn custom<T: Display>(msg: T) -> Self {
+        SerError(msg.to_string())
+    }
+}
+
+fn not_a_struct<T>() -> Result<T, SerError> {
+    Err(ser::Error::custom("models must serialize as a struct or map"))
+}
+
+/// Top-level serializer collecting `(field, value)` pairs from a model
+struct ModelSerializer {
+    column_type: fn(&str) -> ColumnType,
+}
+
+type Columns = Vec<(String, SqlValue)>;
+
+impl Serializer for ModelSerializer {
+    type Ok = Columns;
+    type Error = SerError;
+    type SerializeSeq = Impossible<Columns, SerError>;
+    type SerializeTuple = Impossible<Columns, SerError>;
+    type SerializeTupleStruct = Impossible<Columns, SerError>;
+    type SerializeTupleVariant = Impossible<Columns, SerError>;
+    type SerializeMap = ModelFields;
+    type SerializeStruct = ModelFields;
+    type SerializeStructVariant = Impossible<Columns, SerError>;
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<ModelFields, SerError> {
+        Ok(ModelFields { column_type: self.column_type, columns: Vec::with_capacity(len), key: None })
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<ModelFields, SerError> {
+        Ok(ModelFields { column_type: self.column_type, columns: Vec::with_capacity(len.unwrap_or(0)), key: None })
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(self, _name: &'static str, value: &T) -> Result<Columns, SerError> {
+        value.serialize(self)
+    }
+
+    fn serialize_bool(self, _v: bool) -> Result<Columns, SerError> { not_a_struct() }
+    fn serialize_i8(self, _v: i8) -> Result<Columns, SerError> { not_a_struct() }
+    fn serialize_i16(self, _v: i16) -> Result<Columns, SerError> { not_a_struct() }
+    fn serialize_i32(self, _v: i32) -> Result<Columns, SerError> { not_a_struct() }
+    fn serialize_i64(self, _v: i64) -> Result<Columns, SerError> { not_a_struct() }
+    fn serialize_u8(self, _v: u8) -> Result<Columns, SerError> { not_a_struct() }
+    fn serialize_u16(self, _v: u16) -> Result<Columns, SerError> { not_a_struct() }
+    fn serialize_u32(self, _v: u32) -> Result<Columns, SerError> { not_a_struct() }
+    fn serialize_u64(self, _v: u64) -> Result<Columns, SerError> { not_a_struct() }
+    fn serialize_f32(self, _v: f32) -> Result<Columns, SerError> { not_a_struct() }
+    fn serialize_f64(self, _v: f64) -> Result<Columns, SerError> { not_a_struct() }
+    fn serialize_char(self, _v: char) -> Result<Columns, SerError> { not_a_struct() }
+    fn serialize_str(self, _v: &str) -> Result<Columns, SerError> { not_a_struct() }
+    fn serialize_bytes(self, _v: &[u8]) -> Result<Columns, SerError> { not_a_struct() }
+    fn serialize_none(self) -> Result<Columns, SerError> { not_a_struct() }
+    fn serialize_some<T: ?Sized + Serialize>(self, _value: &T) -> Result<Columns, SerError> { not_a_struct() }
+    fn serialize_unit(self) -> Result<Columns, SerError> { not_a_struct() }
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Columns, SerError> { not_a_struct() }
+
+    fn serialize_unit_variant(self, _name: &'static str, _index: u32, _variant: &'static str) -> Result<Columns, SerError> {
+        not_a_struct()
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self, _name: &'static str, _index: u32, _variant: &'static str, _value: &T,
+    ) -> Result<Columns, SerError> {
+        not_a_struct()
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, SerError> { not_a_struct() }
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, SerError> { not_a_struct() }
+
+    fn serialize_tuple_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeTupleStruct, SerError> {
+        not_a_struct()
+    }
+
+    fn serialize_tuple_variant(
+        self, _name: &'static str, _index: u32, _variant: &'static str, _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, SerError> {
+        not_a_struct()
+    }
+
+    fn serialize_struct_variant(
+        self, _name: &'static str, _index: u32, _variant: &'static str, _len: usize,
+    ) -> Result<Self::SerializeStructVariant, SerError> {
+        not_a_struct()
+    }
+}
+
+struct ModelFields {
+    column_type: fn(&str) -> ColumnType,
+    columns: Columns,
+    key: Option<String>,
+}
+
+impl ModelFields {
+    fn push<T: ?Sized + Serialize>(&mut self, field: String, value: &T) -> Result<(), SerError> {
+        let column_type = (self.column_type)(&field);
+        let value = value.serialize(ColumnSerializer { field: &field, column_type })?;
+        self.columns.push((field, value));
+        Ok(())
+    }
+}
+
+impl ser::SerializeStruct for ModelFields {
+    type Ok = Columns;
+    type Error = SerError;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, key: &'static str, value: &T) -> Result<(), SerError> {
+        self.push(key.to_string(), value)
+    }
+
+    fn end(self) -> Result<Columns, SerError> {
+        Ok(self.columns)
+    }
+}
+
+// Maps show up for models using `#[serde(flatten)]`
+impl ser::SerializeMap for ModelFields {
+    type Ok = Columns;
+    type Error = SerError;
+
+    fn serialize_key<T: ?Sized + Serialize>(&mut self, key: &T) -> Result<(), SerError> {
+        match serde_json::to_value(key) {
+            Ok(serde_json::Value::String(key)) => {
+                self.key = Some(key);
+                Ok(())
+            }
+            _ => Err(ser::Error::custom("model field names must be strings")),
+        }
+    }
+
+    fn serialize_value<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), SerError> {
+        let key = self.key.take().ok_or_else(|| ser::Error::custom("map value without a key"))?;
+        self.push(key, value)
+    }
+
+    fn end(self) -> Result<Columns, SerError> {
+        Ok(self.columns)
+    }
+}
+
+/// Serializer for a single field, producing the value bound for its column
+struct ColumnSerializer<'a> {
+    field: &'a str,
+    column_type: ColumnType,
+}
+
+impl ColumnSerializer<'_> {
+    /// Text values, checked and mapped for enum columns
+    fn text(&self, s: &str) -> Result<SqlValue, SerError> {
+        match self.column_type {
+            ColumnType::TextEnum(names) if names.contains(&s) => Ok(SqlValue::Text(s.to_string())),
+            ColumnType::IntegerEnum(variants) => variants
+                .iter()
+                .find(|(name, _)| *name == s)
+                .map(|(_, discriminant)| SqlValue::Integer(*discriminant))
+                .ok_or_else(|| self.unknown_variant(s)),
+            ColumnType::TextEnum(_) => Err(self.unknown_variant(s)),
+            ColumnType::Decimal(scale) => self.decimal(s, scale),
+            _ => Ok(SqlValue::Text(s.to_string())),
+        }
+    }
+
+    fn decimal(&self, s: &str, scale: u32) -> Result<SqlValue, SerError> {
+        crate::decimal::to_scaled(s, scale).map(SqlValue::Integer).ok_or_else(|| {
+            ser::Error::custom(format!(
+                "{:?} does not fit decimal column `{}` with {} decimal places",
+                s, self.field, scale
+            ))
+        })
+    }
+
+    fn unknown_variant(&self, s: &str) -> SerError {
+        ser::Error::custom(format!("unknown variant {:?} for enum column `{}`", s, self.field))
+    }
+
+    fn integer(&self, i: i64) -> Result<SqlValue, SerError> {
+        match self.column_type {
+            ColumnType::Decimal(scale) => self.decimal(&i.to_string(), scale),
+            _ => self.scalar(SqlValue::Integer(i), &i),
+        }
+    }
+
+    /// Values wider than i64 are stored as decimal text so they round-trip exactly
+    /// INTEGER and REAL columns would coerce that text into a lossy REAL, so they reject it
+    fn wide_integer<I: TryInto<i64> + Display + Copy>(&self, i: I) -> Result<SqlValue, SerError> {
+        match (i.try_into(), self.column_type) {
+            (Ok(i), _) => self.integer(i),
+            (Err(_), ColumnType::Integer | ColumnType::Real | ColumnType::Decimal(_)) => Err(ser::Error::custom(format!(
+                "{} does not fit in {} column `{}`, use a TEXT column",
+                i, self.column_type.sql_type(), self.field
+            ))),
+            (Err(_), _) => self.scalar(SqlValue::Text(i.to_string()), &i),
+        }
+    }
+
+    fn float(&self, f: f64, text: String) -> Result<SqlValue, SerError> {
+        // SQLite renders REAL as TEXT with 15 significant digits, so TEXT columns
+        // get the shortest exact representation instead
+        match self.column_type {
+            ColumnType::Text => Ok(SqlValue::Text(text)),
+            ColumnType::Decimal(scale) => self.decimal(&text, scale),
+            _ => self.scalar(SqlValue::Real(f), &f),
+        }
+    }
+
+    fn scalar(&self, value: SqlValue, display: &dyn Display) -> Result<SqlValue, SerError> {
+        match self.column_type {
+            ColumnType::TextEnum(_) | ColumnType::IntegerEnum(_) => Err(ser::Error::custom(format!(
+                "enum column `{}` only supports unit variants, got {}",
+                self.field, display
+            ))),
+            ColumnType::Decimal(_) => Err(ser::Error::custom(format!(
+                "decimal column `{}` only supports numbers and decimal strings, got {}",
+                self.field, display
+            ))),
+            _ => Ok(value),
+        }
+    }
+
+    /// Nested values are stored as JSON text, byte arrays in BLOB columns as raw bytes
+    fn json(&self, value: serde_json::Value) -> Result<SqlValue, SerError> {
+        match (self.column_type, value) {
+            (_, serde_json::Value::Null) => Ok(SqlValue::Null),
+            (ColumnType::Blob, serde_json::Value::Array(items)) => items
+                .iter()
+                .map(|item| item.as_u64().and_then(|b| u8::try_from(b).ok()))
+                .collect::<Option<Vec<u8>>>()
+                .map(SqlValue::Blob)
+                .ok_or_else(|| ser::Error::custom(format!("BLOB column `{}` must be a byte array", self.field))),
+            (_, value) => {
+                let text = value.to_string();
+                self.scalar(SqlValue::Text(text), &value)
+            }
+        }
+    }
+}
+
+use serde_json::value::Serializer as JsonSerializer;
+
+impl<'a> Serializer for ColumnSerializer<'a> {
+    type Ok = SqlValue;
+    type Error = SerError;
+    type SerializeSeq = JsonCompound<'a, <JsonSerializer as Serializer>::SerializeSeq>;
+    type SerializeTuple = JsonCompound<'a, <JsonSerializer as Serializer>::SerializeTuple>;
+    type SerializeTupleStruct = JsonCompound<'a, <JsonSerializer as Serializer>::SerializeTupleStruct>;
+    type SerializeTupleVariant = JsonCompound<'a, <JsonSerializer as Serializer>::SerializeTupleVariant>;
+    type SerializeMap = JsonCompound<'a, <JsonSerializer as Serializer>::SerializeMap>;
+    type SerializeStruct = JsonCompound<'a, <JsonSerializer as Serializer>::SerializeStruct>;
+    type SerializeStructVariant = JsonCompound<'a, <JsonSerializer as Serializer>::SerializeStructVariant>;
+
+    fn serialize_bool(self, v: bool) -> Result<SqlValue, SerError> {
+        // TEXT columns keep "true"/"false" so the query builder's text binding still matches
+        match self.column_type {
+            ColumnType::Text => Ok(SqlValue::Text(v.to_string())),
+            _ => self.integer(v as i64),
+        }
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<SqlValue, SerError> { self.integer(v.into()) }
+    fn serialize_i16(self, v: i16) -> Result<SqlValue, SerError> { self.integer(v.into()) }
+    fn serialize_i32(self, v: i32) -> Result<SqlValue, SerError> { self.integer(v.into()) }
+    fn serialize_i64(self, v: i64) -> Result<SqlValue, SerError> { self.integer(v) }
+    fn serialize_i128(self, v: i128) -> Result<SqlValue, SerError> { self.wide_integer(v) }
+    fn serialize_u8(self, v: u8) -> Result<SqlValue, SerError> { self.integer(v.into()) }
+    fn serialize_u16(self, v: u16) -> Result<SqlValue, SerError> { self.integer(v.into()) }
+    fn serialize_u32(self, v: u32) -> Result<SqlValue, SerError> { self.integer(v.into()) }
+    fn serialize_u64(self, v: u64) -> Result<SqlValue, SerError> { self.wide_integer(v) }
+    fn serialize_u128(self, v: u128) -> Result<SqlValue, SerError> { self.wide_integer(v) }
+
+    fn serialize_f32(self, v: f32) -> Result<SqlValue, SerError> {
+        self.float(v.into(), v.to_string())
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<SqlValue, SerError> {
+        self.float(v, v.to_string())
+    }
+
+    fn serialize_char(self, v: char) -> Result<SqlValue, SerError> {
+        self.text(v.encode_utf8(&mut [0; 4]))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<SqlValue, SerError> {
+        self.text(v)
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<SqlValue, SerError> {
+        self.scalar(SqlValue::Blob(v.to_vec()), &format_args!("{} bytes", v.len()))
+    }
+
+    fn serialize_none(self) -> Result<SqlValue, SerError> {
+        Ok(SqlValue::Null)
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<SqlValue, SerError> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<SqlValue, SerError> {
+        Ok(SqlValue::Null)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<SqlValue, SerError> {
+        Ok(SqlValue::Null)
+    }
+
+    fn serialize_unit_variant(self, _name: &'static str, _index: u32, variant: &'static str) -> Result<SqlValue, SerError> {
+        self.text(variant)
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(self, _name: &'static str, value: &T) -> Result<SqlValue, SerError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self, name: &'static str, index: u32, variant: &'static str, value: &T,
+    ) -> Result<SqlValue, SerError> {
+        let value = JsonSerializer
+            .serialize_newtype_variant(name, index, variant, value)
+            .map_err(ser::Error::custom)?;
+        self.json(value)
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq, SerError> {
+        JsonCompound::new(self, JsonSerializer.serialize_seq(len))
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple, SerError> {
+        JsonCompound::new(self, JsonSerializer.serialize_tuple(len))
+    }
+
+    fn serialize_tuple_struct(self, name: &'static str, len: usize) -> Result<Self::SerializeTupleStruct, SerError> {
+        JsonCompound::new(self, JsonSerializer.serialize_tuple_struct(name, len))
+    }
+
+    fn serialize_tuple_variant(
+        self, name: &'static str, index: u32, variant: &'static str, len: usize,
+    ) -> Result<Self::SerializeTupleVariant, SerError> {
+        JsonCompound::new(self, JsonSerializer.serialize_tuple_variant(name, index, variant, len))
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap, SerError> {
+        JsonCompound::new(self, JsonSerializer.serialize_map(len))
+    }
+
+    fn serialize_struct(self, name: &'static str, len: usize) -> Result<Self::SerializeStruct, SerError> {
+        JsonCompound::new(self, JsonSerializer.serialize_struct(name, len))
+    }
+
+    fn serialize_struct_variant(
+        self, name: &'static str, index: u32, variant: &'static str, len: usize,
+    ) -> Result<Self::SerializeStructVariant, SerError> {
+        JsonCompound::new(self, JsonSerializer.serialize_struct_variant(name, index, variant, len))
+    }
+}
+
+/// Collects a nested value through serde_json before storing it as JSON text
+struct JsonCompound<'a, S> {
+    column: ColumnSerializer<'a>,
+    inner: S,
+}
+
+impl<'a, S> JsonCompound<'a, S> {
+    fn new(column: ColumnSerializer<'a>, inner: Result<S, serde_json::Error>) -> Result<Self, SerError> {
+        Ok(JsonCompound { column, inner: inner.map_err(ser::Error::custom)? })
+    }
+}
+
+macro_rules! impl_json_compound {
+    ($trait:ident, $method:ident $(, $key:ident)?) => {
+        impl<S> ser::$trait for JsonCompound<'_, S>
+        where S: ser::$trait<Ok = serde_json::Value, Error = serde_json::Error> {
+            type Ok = SqlValue;
+            type Error = SerError;
+
+            fn $method<T: ?Sized + Serialize>(&mut self, $($key: &'static str,)? value: &T) -> Result<(), SerError> {
+                self.inner.$method($($key,)? value).map_err(ser::Error::custom)
+            }
+
+            fn end(self) -> Result<SqlValue, SerError> {
+                let value = self.inner.end().map_err(ser::Error::custom)?;
+                self.column.json(value)
+            }
+        }
+    };
+}
+
+impl_json_compound!(SerializeSeq, serialize_element);
+impl_json_compound!(SerializeTuple, serialize_element);
+impl_json_compound!(SerializeTupleStruct, serialize_field);
+impl_json_compound!(SerializeTupleVariant, serialize_field);
+impl_json_compound!(SerializeStruct, serialize_field, key);
+impl_json_compound!(SerializeStructVariant, serialize_field, key);
+
+impl<S> ser::SerializeMap for JsonCompound<'_, S>
+where S: ser::SerializeMap<Ok = serde_json::Value, Error = serde_json::Error> {
+    type Ok = SqlValue;
+    type Error = SerError;
+
+    fn serialize_key<T: ?Sized + Serialize>(&mut self, key: &T) -> Result<(), SerError> {
+        self.inner.serialize_key(key).map_err(ser::Error::custom)
+    }
+
+    fn serialize_value<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), SerError> {
+        self.inner.serialize_value(value).map_err(ser::Error::custom)
+    }
+
+    fn end(self) -> Result<SqlValue, SerError> {
+        let value = self.inner.end().map_err(ser::Error::custom)?;
+        self.column.json(value)
+    }
+}
@@ -0,0 +1,89 @@
+use rusqlite::{CachedStatement, Connection, Result as SqliteResult};
+use std::cell::RefCell;
+use std::collections::VecDeque;
+
+/// Number of statements rusqlite caches per connection by default
+pub(crate) const DEFAULT_CAPACITY: usize = 16;
+
+/// Hit and miss counts for a database's prepared statement cache
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct StatementCacheStats {
+    /// Statements reused from the cache
+    pub hits: u64,
+    /// Statements that had to be prepared
+    pub misses: u64,
+    /// Maximum number of statements kept
+    pub capacity: usize,
+}
+
+/// Prepares statements through rusqlite's per-connection LRU cache,
+/// mirroring its contents to count hits and misses
+pub(crate) struct StatementCache {
+    state: RefCell<CacheState>,
+}
+
+struct CacheState {
+    /// Cached SQL, most recently used first
+    recent: VecDeque<String>,
+    stats: StatementCacheStats,
+}
+
+impl StatementCache {
+    pub(crate) fn new() -> Self {
+        StatementCache {
+            state: RefCell::new(CacheState {
+                recent: VecDeque::new(),
+                stats: StatementCacheStats { capacity: DEFAULT_CAPACITY, ..Default::default() },
+            }),
+        }
+    }
+
+    pub(crate) fn prepare<'c>(&self, conn: &'c Connection, sql: &str) -> SqliteResult<CachedStatement<'c>> {
+        {
+            let mut state = self.state.borrow_mut();
+            match state.recent.iter().position(|cached| cached == sql) {
+                Some(idx) => {
+                    let cached = state.recent.remove(idx).unwrap_or_default();
+                    state.recent.push_front(cached);
+                    state.stats.hits += 1;
+                }
+                None => {
+                    state.recent.push_front(sql.to_string());
+                    let capacity = state.stats.capacity;
+                    state.recent.truncate(capacity);
+                    state.stats.misses += 1;
+                }
+            }
+        }
+        conn.prepare_cached(sql)
+    }
+
+    pub(crate) fn set_capacity(&self, conn: &Connection, capacity: usize) {
+        conn.set_prepared_statement_cache_capacity(capacity);
+        let mut state = self.state.borrow_mut();
+        state.stats.capacity = capacity;
+        state.recent.truncate(capacity);
+    }
+
+    pub(crate) fn clear(&self, conn: &Connection) {
+        conn.flush_prepared_statement_cache();
+        self.state.borrow_mut().recent.clear();
+    }
+
+    pub(crate) fn stats(&self) -> StatementCacheStats {
+        self.state.borrow().stats
+    }
+
+    pub(crate) fn reset_stats(&self) {
+        let mut state = self.state.borrow_mut();
+        state.stats = StatementCacheStats { capacity: state.stats.capacity, ..Default::default() };
+    }
+}
+
+/// Prepare generated SQL, counting cache hits when it runs for a `Database`
+pub(crate) fn prepare<'a>(conn: &'a Connection, statements: Option<&'a StatementCache>, sql: &str) -> SqliteResult<CachedStatement<'a>> {
+    match statements {
+        Some(statements) => statements.prepare(conn, sql),
+        None => conn.prepare_cached(sql),
+    }
+}
@@ -0,0 +1,115 @@
+use crate::logging::Tracer;
+use crate::model::Model;
+use crate::util::{row_to_model, DeserializeMode};
+use rusqlite::types::Value as SqlValue;
+use rusqlite::{params_from_iter, CachedStatement, Result as SqliteResult, Rows};
+use std::marker::PhantomData;
+use std::time::Instant;
+
+/// A prepared SELECT whose rows are loaded lazily
+///
+/// Call `iter` to walk rows one at a time or `chunks` to receive them in batches;
+/// only the current row or batch is held in memory. Each call re-runs the query.
+pub struct RowStream<'a, T> {
+    stmt: CachedStatement<'a>,
+    sql: String,
+    params: Vec<(Option<String>, SqlValue)>,
+    mode: DeserializeMode,
+    tracer: Tracer,
+    model: PhantomData<fn() -> T>,
+}
+
+impl<'a, T: Model> RowStream<'a, T> {
+    pub(crate) fn new(
+        stmt: CachedStatement<'a>,
+        sql: String,
+        params: Vec<(Option<String>, SqlValue)>,
+        mode: DeserializeMode,
+        tracer: Tracer,
+    ) -> Self {
+        RowStream { stmt, sql, params, mode, tracer, model: PhantomData }
+    }
+
+    /// Run the query and iterate over its rows
+    pub fn iter(&mut self) -> SqliteResult<RowIter<'_, T>> {
+        let started = self.tracer.start();
+        let rows = self.stmt.query(params_from_iter(self.params.iter().map(|(_, value)| value)))?;
+        Ok(RowIter {
+            rows,
+            mode: self.mode,
+            log: Log { tracer: &self.tracer, sql: &self.sql, params: &self.params, started, rows: 0 },
+            model: PhantomData,
+        })
+    }
+
+    /// Run the query and iterate over its rows in batches of up to `size`
+    pub fn chunks(&mut self, size: usize) -> SqliteResult<RowChunks<'_, T>> {
+        Ok(RowChunks { rows: self.iter()?, size: size.max(1) })
+    }
+}
+
+/// Iterator over the rows of a `RowStream`
+/// The query is logged when the iterator is dropped, with the number of rows read
+pub struct RowIter<'s, T> {
+    rows: Rows<'s>,
+    mode: DeserializeMode,
+    log: Log<'s>,
+    model: PhantomData<fn() -> T>,
+}
+
+/// Logging state for a running SELECT
+struct Log<'s> {
+    tracer: &'s Tracer,
+    sql: &'s str,
+    params: &'s [(Option<String>, SqlValue)],
+    started: Option<Instant>,
+    rows: usize,
+}
+
+impl Drop for Log<'_> {
+    fn drop(&mut self) {
+        let params = self.params.iter().map(|(field, value)| (field.as_deref(), value));
+        self.tracer.finish(self.started, self.sql, params, self.rows);
+    }
+}
+
+impl<T: Model> Iterator for RowIter<'_, T> {
+    type Item = SqliteResult<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.rows.next() {
+            Ok(Some(row)) => {
+                self.log.rows += 1;
+                Some(row_to_model(row, self.mode))
+            }
+            Ok(None) => None,
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+/// Iterator over the rows of a `RowStream` in fixed-size batches
+/// The last batch may be shorter; a row that fails to load is returned as the error
+/// in place of its batch
+pub struct RowChunks<'s, T> {
+    rows: RowIter<'s, T>,
+    size: usize,
+}
+
+impl<T: Model> Iterator for RowChunks<'_, T> {
+    type Item = SqliteResult<Vec<T>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut batch = Vec::with_capacity(self.size);
+        for row in self.rows.by_ref() {
+            match row {
+                Ok(model) => batch.push(model),
+                Err(e) => return Some(Err(e)),
+            }
+            if batch.len() == self.size {
+                break;
+            }
+        }
+        (!batch.is_empty()).then_some(Ok(batch))
+    }
+}
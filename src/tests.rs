@@ -55,13 +55,13 @@ fn test_insert_select() {
     let db = Database::connect_in_memory().unwrap();
     db.create_table::<User>().unwrap();
 
-    let user = User {
+    let mut user = User {
         id: 1,
         name: "Alice".to_string(),
         email: "alice@example.com".to_string(),
     };
 
-    let insert_result = db.insert(&user);
+    let insert_result = db.insert(&mut user);
     assert!(insert_result.is_ok());
 
     let users = db.select_all::<User>().unwrap();
@@ -75,7 +75,7 @@ fn test_insert_multiple() {
     let db = Database::connect_in_memory().unwrap();
     db.create_table::<User>().unwrap();
 
-    let users_to_insert = vec![
+    let mut users_to_insert = vec![
         User {
             id: 1,
             name: "Alice".to_string(),
@@ -93,7 +93,7 @@ fn test_insert_multiple() {
         },
     ];
 
-    for user in &users_to_insert {
+    for user in &mut users_to_insert {
         db.insert(user).unwrap();
     }
 
@@ -106,13 +106,13 @@ fn test_find_by_id() {
     let db = Database::connect_in_memory().unwrap();
     db.create_table::<User>().unwrap();
 
-    let user = User {
+    let mut user = User {
         id: 42,
         name: "Alice".to_string(),
         email: "alice@example.com".to_string(),
     };
 
-    db.insert(&user).unwrap();
+    db.insert(&mut user).unwrap();
 
     let found_user = db.find_by_id::<User>(42).unwrap();
     assert!(found_user.is_some());
@@ -131,13 +131,13 @@ fn test_delete() {
     let db = Database::connect_in_memory().unwrap();
     db.create_table::<User>().unwrap();
 
-    let user = User {
+    let mut user = User {
         id: 1,
         name: "Alice".to_string(),
         email: "alice@example.com".to_string(),
     };
 
-    db.insert(&user).unwrap();
+    db.insert(&mut user).unwrap();
 
     let users_before = db.select_all::<User>().unwrap();
     assert_eq!(users_before.len(), 1);
@@ -159,21 +159,21 @@ fn test_update() {
     let db = Database::connect_in_memory().unwrap();
     db.create_table::<User>().unwrap();
 
-    let user = User {
+    let mut user = User {
         id: 1,
         name: "Alice".to_string(),
         email: "alice@example.com".to_string(),
     };
 
-    db.insert(&user).unwrap();
+    db.insert(&mut user).unwrap();
 
-    let updated_user = User {
+    let mut updated_user = User {
         id: 1,
         name: "Alice Smith".to_string(),
         email: "alice.smith@example.com".to_string(),
     };
 
-    let update_result = db.update(&updated_user);
+    let update_result = db.update(&mut updated_user);
     assert!(update_result.is_ok());
     assert_eq!(update_result.unwrap(), 1);
 
@@ -187,13 +187,13 @@ fn test_query_builder_where_eq() {
     let db = Database::connect_in_memory().unwrap();
     db.create_table::<User>().unwrap();
 
-    db.insert(&User {
+    db.insert(&mut User {
         id: 1,
         name: "Alice".to_string(),
         email: "alice@example.com".to_string(),
     }).unwrap();
 
-    db.insert(&User {
+    db.insert(&mut User {
         id: 2,
         name: "Bob".to_string(),
         email: "bob@example.com".to_string(),
@@ -213,7 +213,7 @@ fn test_query_builder_limit() {
     db.create_table::<User>().unwrap();
 
     for i in 1..=5 {
-        db.insert(&User {
+        db.insert(&mut User {
             id: i,
             name: format!("User{}", i),
             email: format!("user{}@example.com", i),
@@ -232,19 +232,19 @@ fn test_query_builder_order_by() {
     let db = Database::connect_in_memory().unwrap();
     db.create_table::<User>().unwrap();
 
-    db.insert(&User {
+    db.insert(&mut User {
         id: 1,
         name: "Charlie".to_string(),
         email: "charlie@example.com".to_string(),
     }).unwrap();
 
-    db.insert(&User {
+    db.insert(&mut User {
         id: 2,
         name: "Alice".to_string(),
         email: "alice@example.com".to_string(),
     }).unwrap();
 
-    db.insert(&User {
+    db.insert(&mut User {
         id: 3,
         name: "Bob".to_string(),
         email: "bob@example.com".to_string(),
@@ -264,7 +264,7 @@ fn test_query_builder_fetch_one() {
     let db = Database::connect_in_memory().unwrap();
     db.create_table::<User>().unwrap();
 
-    db.insert(&User {
+    db.insert(&mut User {
         id: 1,
         name: "Alice".to_string(),
         email: "alice@example.com".to_string(),
@@ -283,7 +283,7 @@ fn test_sql_injection_protection() {
     let db = Database::connect_in_memory().unwrap();
     db.create_table::<User>().unwrap();
 
-    db.insert(&User {
+    db.insert(&mut User {
         id: 1,
         name: "Alice".to_string(),
         email: "alice@example.com".to_string(),
@@ -310,20 +310,20 @@ fn test_multiple_models() {
     db.create_table::<User>().unwrap();
     db.create_table::<Post>().unwrap();
 
-    let user = User {
+    let mut user = User {
         id: 1,
         name: "Alice".to_string(),
         email: "alice@example.com".to_string(),
     };
-    db.insert(&user).unwrap();
+    db.insert(&mut user).unwrap();
 
-    let post = Post {
+    let mut post = Post {
         id: 1,
         title: "My First Post".to_string(),
         content: "Hello, World!".to_string(),
         author_id: 1,
     };
-    db.insert(&post).unwrap();
+    db.insert(&mut post).unwrap();
 
     let users = db.select_all::<User>().unwrap();
     let posts = db.select_all::<Post>().unwrap();
@@ -338,7 +338,7 @@ fn test_drop_table() {
     let db = Database::connect_in_memory().unwrap();
     db.create_table::<User>().unwrap();
 
-    db.insert(&User {
+    db.insert(&mut User {
         id: 1,
         name: "Alice".to_string(),
         email: "alice@example.com".to_string(),
@@ -396,3 +396,2092 @@ fn test_loose_type_conversion() {
     assert_eq!(results[0].integer_field, 42); // This would fail without the fix
     assert_eq!(results[0].string_field, "hello");
 }
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct Event {
+    id: i32,
+    name: String,
+    #[serde(with = "crate::datetime::iso8601")]
+    starts_at: std::time::SystemTime,
+    #[serde(with = "crate::datetime::unix::option")]
+    cancelled_at: Option<std::time::SystemTime>,
+}
+
+impl Model for Event {
+    fn table_name() -> &'static str {
+        "events"
+    }
+
+    fn fields() -> &'static [&'static str] {
+        &["id", "name", "starts_at", "cancelled_at"]
+    }
+
+    fn column_type(field: &str) -> crate::ColumnType {
+        match field {
+            "cancelled_at" => crate::ColumnType::Integer,
+            _ => crate::ColumnType::Text,
+        }
+    }
+}
+
+fn event_at(id: i32, secs: u64, cancelled_secs: Option<u64>) -> Event {
+    let at = |s| std::time::UNIX_EPOCH + std::time::Duration::from_secs(s);
+    Event {
+        id,
+        name: format!("Event{}", id),
+        starts_at: at(secs) + std::time::Duration::from_millis(250),
+        cancelled_at: cancelled_secs.map(at),
+    }
+}
+
+#[test]
+fn test_iso8601_format_and_parse() {
+    use crate::datetime::iso8601;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    let t = UNIX_EPOCH + Duration::new(951_782_400, 5_000_000);
+    assert_eq!(iso8601::format(&t).as_deref(), Some("2000-02-29T00:00:00.005000000Z"));
+    assert_eq!(iso8601::parse::<SystemTime>("2000-02-29T00:00:00.005Z"), Some(t));
+    assert_eq!(iso8601::parse::<SystemTime>("2000-02-29 02:00:00.005+02:00"), Some(t));
+    assert_eq!(iso8601::parse::<SystemTime>("1969-12-31T23:59:59Z"), Some(UNIX_EPOCH - Duration::from_secs(1)));
+    assert_eq!(iso8601::parse::<SystemTime>("2001-02-29T00:00:00Z"), None);
+    assert_eq!(iso8601::parse::<SystemTime>("not a date"), None);
+
+    // Offsets must be valid hours and minutes
+    assert_eq!(iso8601::parse::<SystemTime>("2000-02-29T00:00:00+23:59"), Some(t - Duration::new(86_340, 5_000_000)));
+    assert_eq!(iso8601::parse::<SystemTime>("2000-02-29T00:00:00+24:00"), None);
+    assert_eq!(iso8601::parse::<SystemTime>("2000-02-29T00:00:00-01:60"), None);
+    assert_eq!(iso8601::parse::<SystemTime>("2000-02-29T00:00:00+99:99"), None);
+
+    // Only four-digit years keep the text fixed-width, so others are not formatted
+    let last = UNIX_EPOCH + Duration::from_secs(253_402_300_799);
+    assert_eq!(iso8601::format(&last).as_deref(), Some("9999-12-31T23:59:59.000000000Z"));
+    assert_eq!(iso8601::format(&(last + Duration::from_secs(1))), None);
+    assert_eq!(iso8601::format(&(UNIX_EPOCH - Duration::from_secs(62_167_219_201))), None);
+}
+
+#[test]
+fn test_timestamp_roundtrip_and_range_queries() {
+    let db = Database::connect_in_memory().unwrap();
+    db.create_table::<Event>().unwrap();
+
+    // 999 and 1000 seconds compare incorrectly as plain text
+    db.insert(&mut event_at(1, 999, Some(999))).unwrap();
+    db.insert(&mut event_at(2, 1000, Some(1000))).unwrap();
+    db.insert(&mut event_at(3, 86_400 * 365, None)).unwrap();
+
+    let events = db.select_all::<Event>().unwrap();
+    assert_eq!(events, vec![event_at(1, 999, Some(999)), event_at(2, 1000, Some(1000)), event_at(3, 86_400 * 365, None)]);
+
+    let cutoff = event_at(0, 999, Some(999));
+    let later = db.query::<Event>()
+        .where_time_gt("starts_at", cutoff.starts_at)
+        .order_by("id", true)
+        .fetch::<Event>()
+        .unwrap();
+    assert_eq!(later.iter().map(|e| e.id).collect::<Vec<_>>(), vec![2, 3]);
+
+    let cancelled_late = db.query::<Event>()
+        .where_time_gt("cancelled_at", cutoff.cancelled_at.unwrap())
+        .fetch::<Event>()
+        .unwrap();
+    assert_eq!(cancelled_late.iter().map(|e| e.id).collect::<Vec<_>>(), vec![2]);
+
+    let exact = db.query::<Event>().where_time_eq("starts_at", event_at(0, 1000, None).starts_at).fetch::<Event>().unwrap();
+    assert_eq!(exact.iter().map(|e| e.id).collect::<Vec<_>>(), vec![2]);
+    let early = db.query::<Event>().where_time_lt("cancelled_at", cutoff.cancelled_at.unwrap()).fetch::<Event>().unwrap();
+    assert!(early.is_empty());
+
+    // A year past 9999 would sort before earlier years as text would sort before earlier years as text
+    let mut far = event_at(4, 253_402_300_800, None);
+    assert!(matches!(db.insert(&mut far), Err(rusqlite::Error::ToSqlConversionFailure(_))));
+    assert_eq!(db.select_all::<Event>().unwrap().len(), 3);
+    let result = db.query::<Event>().where_time_gt("starts_at", far.starts_at).fetch::<Event>();
+    assert!(matches!(result, Err(rusqlite::Error::ToSqlConversionFailure(_))));
+}
+
+#[cfg(feature = "chrono")]
+#[test]
+fn test_chrono_columns() {
+    use chrono::{DateTime, NaiveDate, Utc};
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Invoice {
+        id: i32,
+        #[serde(with = "crate::datetime::iso8601")]
+        issued_at: DateTime<Utc>,
+        #[serde(with = "crate::datetime::unix")]
+        due_on: NaiveDate,
+    }
+
+    impl Model for Invoice {
+        fn table_name() -> &'static str {
+            "invoices"
+        }
+
+        fn fields() -> &'static [&'static str] {
+            &["id", "issued_at", "due_on"]
+        }
+
+        fn column_type(field: &str) -> crate::ColumnType {
+            match field {
+                "due_on" => crate::ColumnType::Integer,
+                _ => crate::ColumnType::Text,
+            }
+        }
+    }
+
+    let db = Database::connect_in_memory().unwrap();
+    db.create_table::<Invoice>().unwrap();
+
+    let mut invoice = Invoice {
+        id: 1,
+        issued_at: DateTime::from_timestamp(1_700_000_000, 123_456_789).unwrap(),
+        due_on: NaiveDate::from_ymd_opt(2023, 12, 14).unwrap(),
+    };
+    db.insert(&mut invoice).unwrap();
+
+    let found = db.find_by_id::<Invoice>(1).unwrap().unwrap();
+    assert_eq!(found, invoice);
+
+    let cutoff = NaiveDate::from_ymd_opt(2023, 12, 1).unwrap();
+    let due_after = db.query::<Invoice>()
+        .where_time_gt("due_on", cutoff)
+        .fetch::<Invoice>()
+        .unwrap();
+    assert_eq!(due_after.len(), 1);
+
+    // chrono's Display form does not sort against the stored text, the typed comparison does
+    let issued = invoice.issued_at;
+    let later = |at: DateTime<Utc>| db.query::<Invoice>().where_time_gt("issued_at", at).fetch::<Invoice>().unwrap().len();
+    assert_eq!(later(issued - chrono::Duration::seconds(1)), 1);
+    assert_eq!(later(issued), 0);
+    assert_eq!(db.query::<Invoice>().where_time_eq("issued_at", issued).fetch::<Invoice>().unwrap().len(), 1);
+    assert_eq!(crate::datetime::iso8601::format(&cutoff).as_deref(), Some("2023-12-01"));
+}
+
+#[cfg(feature = "time")]
+#[test]
+fn test_time_columns() {
+    use time::{Date, Month, OffsetDateTime, UtcOffset};
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Shift {
+        id: i32,
+        #[serde(with = "crate::datetime::iso8601")]
+        starts_at: OffsetDateTime,
+        #[serde(with = "crate::datetime::iso8601::option")]
+        day: Option<Date>,
+    }
+
+    impl Model for Shift {
+        fn table_name() -> &'static str {
+            "shifts"
+        }
+
+        fn fields() -> &'static [&'static str] {
+            &["id", "starts_at", "day"]
+        }
+    }
+
+    let db = Database::connect_in_memory().unwrap();
+    db.create_table::<Shift>().unwrap();
+
+    // Stored in UTC regardless of the original offset
+    let starts_at = OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap()
+        .to_offset(UtcOffset::from_hms(5, 30, 0).unwrap());
+    let mut shift = Shift {
+        id: 1,
+        starts_at,
+        day: Some(Date::from_calendar_date(2023, Month::November, 14).unwrap()),
+    };
+    db.insert(&mut shift).unwrap();
+    db.insert(&mut Shift { id: 2, starts_at, day: None }).unwrap();
+
+    let found = db.find_by_id::<Shift>(1).unwrap().unwrap();
+    assert_eq!(found.starts_at, starts_at);
+    assert_eq!(found.starts_at.offset(), UtcOffset::UTC);
+    assert_eq!(found.day, shift.day);
+    assert_eq!(db.find_by_id::<Shift>(2).unwrap().unwrap().day, None);
+
+    // Offsets are normalised to UTC before comparing
+    let earlier = db.query::<Shift>().where_time_lt("starts_at", starts_at + time::Duration::seconds(1)).fetch::<Shift>().unwrap();
+    assert_eq!(earlier.len(), 2);
+    let day = db.query::<Shift>().where_time_eq("day", shift.day.unwrap()).fetch::<Shift>().unwrap();
+    assert_eq!(day.iter().map(|s| s.id).collect::<Vec<_>>(), vec![1]);
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct Article {
+    id: i32,
+    title: String,
+    created_at: Option<String>,
+    updated_at: Option<i64>,
+}
+
+impl Model for Article {
+    fn table_name() -> &'static str {
+        "articles"
+    }
+
+    fn fields() -> &'static [&'static str] {
+        &["id", "title", "created_at", "updated_at"]
+    }
+
+    fn column_type(field: &str) -> crate::ColumnType {
+        match field {
+            "updated_at" => crate::ColumnType::Integer,
+            _ => crate::ColumnType::Text,
+        }
+    }
+
+    fn created_at_field() -> Option<&'static str> {
+        Some("created_at")
+    }
+
+    fn updated_at_field() -> Option<&'static str> {
+        Some("updated_at")
+    }
+}
+
+fn article(id: i32, title: &str) -> Article {
+    Article {
+        id,
+        title: title.to_string(),
+        created_at: None,
+        updated_at: None,
+    }
+}
+
+#[test]
+fn test_managed_timestamps() {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Arc;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    let secs = Arc::new(AtomicU64::new(1_700_000_000));
+    let clock_secs = Arc::clone(&secs);
+    let db = Database::connect_in_memory().unwrap()
+        .with_clock(move || UNIX_EPOCH + Duration::from_secs(clock_secs.load(Ordering::SeqCst)));
+    db.create_table::<Article>().unwrap();
+
+    db.insert(&mut article(1, "Hello")).unwrap();
+    db.insert(&mut article(2, "World")).unwrap();
+
+    let inserted = db.find_by_id::<Article>(1).unwrap().unwrap();
+    assert_eq!(inserted.created_at.as_deref(), Some("2023-11-14T22:13:20.000000000Z"));
+    assert_eq!(inserted.updated_at, Some(1_700_000_000));
+
+    // Single update only touches updated_at, even if created_at was cleared
+    secs.store(1_700_000_100, Ordering::SeqCst);
+    db.update(&mut article(1, "Hello again")).unwrap();
+
+    let updated = db.find_by_id::<Article>(1).unwrap().unwrap();
+    assert_eq!(updated.title, "Hello again");
+    assert_eq!(updated.created_at, inserted.created_at);
+    assert_eq!(updated.updated_at, Some(1_700_000_100));
+
+    // Bulk updates also bump updated_at
+    secs.store(1_700_000_200, Ordering::SeqCst);
+    let changed = db.query::<Article>()
+        .where_eq("id", 2)
+        .set("title", "Everyone")
+        .update::<Article>()
+        .unwrap();
+    assert_eq!(changed, 1);
+
+    let bulk = db.find_by_id::<Article>(2).unwrap().unwrap();
+    assert_eq!(bulk.title, "Everyone");
+    assert_eq!(bulk.updated_at, Some(1_700_000_200));
+    assert_eq!(db.find_by_id::<Article>(1).unwrap().unwrap().updated_at, Some(1_700_000_100));
+}
+
+#[test]
+fn test_bulk_update() {
+    let db = Database::connect_in_memory().unwrap();
+    db.create_table::<Post>().unwrap();
+
+    for i in 1..=3 {
+        db.insert(&mut Post {
+            id: i,
+            title: format!("Post{}", i),
+            content: "draft".to_string(),
+            author_id: if i == 3 { 2 } else { 1 },
+        }).unwrap();
+    }
+
+    let changed = db.query::<Post>()
+        .where_eq("author_id", 1)
+        .set("content", "published")
+        .update::<Post>()
+        .unwrap();
+    assert_eq!(changed, 2);
+
+    let published = db.query::<Post>()
+        .where_eq("content", "published")
+        .fetch::<Post>()
+        .unwrap();
+    assert_eq!(published.iter().map(|p| p.id).collect::<Vec<_>>(), vec![1, 2]);
+
+    // Nothing to set is rejected
+    assert!(db.query::<Post>().update::<Post>().is_err());
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct Customer {
+    id: i32,
+    name: String,
+    deleted_at: Option<String>,
+}
+
+impl Model for Customer {
+    fn table_name() -> &'static str {
+        "customers"
+    }
+
+    fn fields() -> &'static [&'static str] {
+        &["id", "name", "deleted_at"]
+    }
+
+    fn deleted_at_field() -> Option<&'static str> {
+        Some("deleted_at")
+    }
+}
+
+#[test]
+fn test_soft_delete() {
+    let db = Database::connect_in_memory().unwrap();
+    db.create_table::<Customer>().unwrap();
+
+    for (id, name) in [(1, "Alice"), (2, "Bob"), (3, "Carol")] {
+        db.insert(&mut Customer { id, name: name.to_string(), deleted_at: None }).unwrap();
+    }
+
+    assert_eq!(db.delete::<Customer>(2).unwrap(), 1);
+    // Already trashed rows are not deleted again
+    assert_eq!(db.delete::<Customer>(2).unwrap(), 0);
+
+    let ids = |customers: Vec<Customer>| customers.iter().map(|c| c.id).collect::<Vec<_>>();
+    assert_eq!(ids(db.select_all::<Customer>().unwrap()), vec![1, 3]);
+    assert!(db.find_by_id::<Customer>(2).unwrap().is_none());
+    assert_eq!(ids(db.query::<Customer>().fetch::<Customer>().unwrap()), vec![1, 3]);
+    assert_eq!(ids(db.query::<Customer>().with_trashed().fetch::<Customer>().unwrap()), vec![1, 2, 3]);
+
+    let trashed = db.query::<Customer>().only_trashed().fetch::<Customer>().unwrap();
+    assert_eq!(trashed.len(), 1);
+    assert_eq!(trashed[0].name, "Bob");
+    assert!(trashed[0].deleted_at.is_some());
+
+    // Bulk updates skip trashed rows unless asked
+    assert_eq!(db.query::<Customer>().set("name", "Renamed").update::<Customer>().unwrap(), 2);
+
+    assert_eq!(db.restore::<Customer>(2).unwrap(), 1);
+    assert_eq!(db.find_by_id::<Customer>(2).unwrap().unwrap().name, "Bob");
+
+    assert_eq!(db.force_delete::<Customer>(2).unwrap(), 1);
+    assert_eq!(ids(db.query::<Customer>().with_trashed().fetch::<Customer>().unwrap()), vec![1, 3]);
+
+    // Restore needs a soft-deletable model
+    assert!(db.restore::<User>(1).is_err());
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct Attachment {
+    id: i32,
+    name: String,
+    data: Vec<u8>,
+    thumbnail: Option<Vec<u8>>,
+}
+
+impl Model for Attachment {
+    fn table_name() -> &'static str {
+        "attachments"
+    }
+
+    fn fields() -> &'static [&'static str] {
+        &["id", "name", "data", "thumbnail"]
+    }
+
+    fn column_type(field: &str) -> crate::ColumnType {
+        match field {
+            "data" | "thumbnail" => crate::ColumnType::Blob,
+            _ => crate::ColumnType::Text,
+        }
+    }
+}
+
+#[test]
+fn test_blob_roundtrip() {
+    let db = Database::connect_in_memory().unwrap();
+    db.create_table::<Attachment>().unwrap();
+
+    let mut attachment = Attachment {
+        id: 1,
+        name: "logo.png".to_string(),
+        data: (0..=255).collect(),
+        thumbnail: None,
+    };
+    db.insert(&mut attachment).unwrap();
+
+    let stored_type: String = db.conn
+        .query_row("SELECT typeof(data) FROM attachments WHERE id = 1", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(stored_type, "blob");
+    assert_eq!(db.find_by_id::<Attachment>(1).unwrap().unwrap(), attachment);
+
+    let mut updated = Attachment {
+        thumbnail: Some(vec![0, 1, 2]),
+        data: Vec::new(),
+        ..attachment
+    };
+    db.update(&mut updated).unwrap();
+    assert_eq!(db.find_by_id::<Attachment>(1).unwrap().unwrap(), updated);
+}
+
+#[test]
+fn test_incremental_blob_io() {
+    use std::io::{Read, Seek, SeekFrom, Write};
+
+    let db = Database::connect_in_memory().unwrap();
+    db.create_table::<Attachment>().unwrap();
+    db.insert(&mut Attachment {
+        id: 7,
+        name: "large.bin".to_string(),
+        data: Vec::new(),
+        thumbnail: None,
+    }).unwrap();
+
+    assert_eq!(db.reserve_blob::<Attachment>("data", 7, 8).unwrap(), 1);
+
+    let mut blob = db.open_blob::<Attachment>("data", 7, false).unwrap();
+    blob.write_all(b"pebb").unwrap();
+    blob.write_all(b"le!!").unwrap();
+    // Blobs cannot grow through incremental I/O
+    assert!(blob.write_all(b"overflow").is_err());
+
+    blob.seek(SeekFrom::Start(0)).unwrap();
+    let mut contents = Vec::new();
+    blob.read_to_end(&mut contents).unwrap();
+    assert_eq!(contents, b"pebble!!");
+    drop(blob);
+
+    assert_eq!(db.find_by_id::<Attachment>(7).unwrap().unwrap().data, b"pebble!!");
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+struct Settings {
+    theme: String,
+    font_size: i32,
+    notifications: bool,
+    tags: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct Profile {
+    id: i32,
+    settings: crate::Json<Settings>,
+    history: Option<crate::Json<Vec<i64>>>,
+}
+
+impl Model for Profile {
+    fn table_name() -> &'static str {
+        "profiles"
+    }
+
+    fn fields() -> &'static [&'static str] {
+        &["id", "settings", "history"]
+    }
+}
+
+fn profile(id: i32, theme: &str, font_size: i32, tags: &[&str]) -> Profile {
+    Profile {
+        id,
+        settings: crate::Json(Settings {
+            theme: theme.to_string(),
+            font_size,
+            notifications: id % 2 == 0,
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+        }),
+        history: if id == 1 { Some(crate::Json(vec![3, 1, 4])) } else { None },
+    }
+}
+
+#[test]
+fn test_json_columns() {
+    let db = Database::connect_in_memory().unwrap();
+    db.create_table::<Profile>().unwrap();
+
+    db.insert(&mut profile(1, "dark", 12, &["admin"])).unwrap();
+    db.insert(&mut profile(2, "light", 16, &["beta", "admin"])).unwrap();
+    db.insert(&mut profile(3, "dark", 18, &[])).unwrap();
+
+    let stored: String = db.conn
+        .query_row("SELECT json_extract(settings, '$.theme') FROM profiles WHERE id = 2", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(stored, "light");
+    assert_eq!(db.find_by_id::<Profile>(1).unwrap().unwrap(), profile(1, "dark", 12, &["admin"]));
+
+    let ids = |query: QueryBuilder<'_>| {
+        query.order_by("id", true).fetch::<Profile>().unwrap().iter().map(|p| p.id).collect::<Vec<_>>()
+    };
+    assert_eq!(ids(db.query::<Profile>().where_json_eq("settings", "$.theme", "dark")), vec![1, 3]);
+    assert_eq!(ids(db.query::<Profile>().where_json_gt("settings", "$.font_size", 12)), vec![2, 3]);
+    assert_eq!(ids(db.query::<Profile>().where_json_lt("settings", "$.font_size", 18)), vec![1, 2]);
+    assert_eq!(ids(db.query::<Profile>().where_json_eq("settings", "$.notifications", true)), vec![2]);
+    assert_eq!(ids(db.query::<Profile>().where_json_eq("settings", "$.tags[0]", "admin")), vec![1]);
+    assert_eq!(ids(db.query::<Profile>().where_json_eq("history", "$[2]", 4)), vec![1]);
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+enum TicketStatus {
+    Open,
+    Closed,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+enum Priority {
+    Low,
+    High,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+enum Resolution {
+    Fixed,
+    Duplicate(i32),
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct Ticket {
+    id: i32,
+    status: TicketStatus,
+    priority: Priority,
+    resolution: Option<Resolution>,
+}
+
+impl Model for Ticket {
+    fn table_name() -> &'static str {
+        "tickets"
+    }
+
+    fn fields() -> &'static [&'static str] {
+        &["id", "status", "priority", "resolution"]
+    }
+
+    fn column_type(field: &str) -> crate::ColumnType {
+        match field {
+            "status" => crate::ColumnType::TextEnum(&["Open", "Closed"]),
+            "priority" => crate::ColumnType::IntegerEnum(&[("Low", 1), ("High", 10)]),
+            "resolution" => crate::ColumnType::TextEnum(&["Fixed", "Duplicate"]),
+            _ => crate::ColumnType::Text,
+        }
+    }
+}
+
+#[test]
+fn test_enum_columns() {
+    let db = Database::connect_in_memory().unwrap();
+    db.create_table::<Ticket>().unwrap();
+
+    let mut ticket = Ticket { id: 1, status: TicketStatus::Open, priority: Priority::High, resolution: None };
+    db.insert(&mut ticket).unwrap();
+    assert_eq!(db.find_by_id::<Ticket>(1).unwrap().unwrap(), ticket);
+
+    let stored: (String, i64) = db.conn
+        .query_row("SELECT status, priority FROM tickets WHERE id = 1", [], |row| Ok((row.get(0)?, row.get(1)?)))
+        .unwrap();
+    assert_eq!(stored, ("Open".to_string(), 10));
+
+    let high = db.query::<Ticket>().where_eq("priority", 10).fetch::<Ticket>().unwrap();
+    assert_eq!(high.len(), 1);
+
+    // Integer enum columns can be queried and set by variant name
+    let high = db.query::<Ticket>().where_eq("priority", "High").fetch::<Ticket>().unwrap();
+    assert_eq!(high.len(), 1);
+    assert_eq!(high[0], ticket);
+    assert_eq!(db.query::<Ticket>().where_gt("priority", "Low").fetch::<Ticket>().unwrap().len(), 1);
+    assert!(db.query::<Ticket>().where_lt("priority", "High").fetch::<Ticket>().unwrap().is_empty());
+    db.query::<Ticket>().where_eq("priority", "High").set("priority", "Low").update::<Ticket>().unwrap();
+    assert_eq!(db.find_by_id::<Ticket>(1).unwrap().unwrap().priority, Priority::Low);
+    db.query::<Ticket>().set("priority", "High").update::<Ticket>().unwrap();
+
+    // The CHECK constraint rejects values outside the enum
+    assert!(db.conn.execute("UPDATE tickets SET status = 'Pending' WHERE id = 1", []).is_err());
+    assert!(db.conn.execute("UPDATE tickets SET priority = 5 WHERE id = 1", []).is_err());
+
+    // Data-carrying variants are rejected with the column name
+    let err = db.insert(&mut Ticket {
+        id: 2,
+        status: TicketStatus::Closed,
+        priority: Priority::Low,
+        resolution: Some(Resolution::Duplicate(1)),
+    }).unwrap_err();
+    assert!(err.to_string().contains("`resolution`"), "{}", err);
+}
+
+#[test]
+fn test_unknown_enum_value_names_column() {
+    let db = Database::connect_in_memory().unwrap();
+
+    // Legacy table without the CHECK constraints
+    db.conn.execute(
+        "CREATE TABLE tickets (id INTEGER PRIMARY KEY, status TEXT, priority INTEGER, resolution TEXT)",
+        [],
+    ).unwrap();
+    db.conn.execute("INSERT INTO tickets VALUES (1, 'Open', 7, NULL)", []).unwrap();
+    db.conn.execute("INSERT INTO tickets VALUES (2, 'Archived', 1, NULL)", []).unwrap();
+
+    let err = db.find_by_id::<Ticket>(1).unwrap_err();
+    assert!(err.to_string().contains("unknown enum value 7 in column `priority` of row with id = 1"), "{}", err);
+
+    let err = db.find_by_id::<Ticket>(2).unwrap_err();
+    assert!(err.to_string().contains("unknown enum value \"Archived\" in column `status` of row with id = 2"), "{}", err);
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct Reading {
+    id: i32,
+    level: i8,
+    value: f64,
+}
+
+impl Model for Reading {
+    fn table_name() -> &'static str {
+        "readings"
+    }
+
+    fn fields() -> &'static [&'static str] {
+        &["id", "level", "value"]
+    }
+
+    fn column_type(field: &str) -> crate::ColumnType {
+        match field {
+            "level" => crate::ColumnType::Integer,
+            "value" => crate::ColumnType::Real,
+            _ => crate::ColumnType::Text,
+        }
+    }
+}
+
+#[test]
+fn test_strict_deserialize_mode() {
+    use crate::DeserializeMode;
+
+    let db = Database::connect_in_memory().unwrap();
+    db.create_table::<Reading>().unwrap();
+    db.conn.execute("INSERT INTO readings VALUES (1, 5, 1.5)", []).unwrap();
+    db.conn.execute("INSERT INTO readings VALUES (2, 300, 2.5)", []).unwrap();
+    db.conn.execute("INSERT INTO readings VALUES (4, 2, 9e999)", []).unwrap();
+
+    // Loose mode keeps the old coercions
+    let loose = db.select_all::<Reading>().unwrap();
+    assert_eq!(loose[1].level, 300i64 as i8);
+    assert_eq!(loose[2].value, 0.0);
+
+    let strict_readings = |id: i32| {
+        db.query::<Reading>()
+            .deserialize_mode(DeserializeMode::Strict)
+            .where_eq("id", id)
+            .fetch::<Reading>()
+    };
+    assert_eq!(strict_readings(1).unwrap()[0].level, 5);
+    let err = strict_readings(4).unwrap_err().to_string();
+    assert!(err.contains("non-finite float inf in column `value` of row with id = 4"), "{}", err);
+
+    // Strict mode can also be the database default
+    let strict_db = Database::connect_in_memory().unwrap().with_deserialize_mode(DeserializeMode::Strict);
+    strict_db.create_table::<Reading>().unwrap();
+    strict_db.conn.execute("INSERT INTO readings VALUES (2, 300, 2.5)", []).unwrap();
+    let err = strict_db.select_all::<Reading>().unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Conversion error from type Integer at index: 1, integer 300 out of range for i8 in column `level` of row with id = 2"
+    );
+
+    // Integers in TEXT columns must be in canonical form
+    db.create_table::<Post>().unwrap();
+    db.conn.execute("INSERT INTO posts VALUES (1, 'a', 'b', '7'), (2, 'a', 'b', '+7')", []).unwrap();
+    assert_eq!(db.select_all::<Post>().unwrap()[1].author_id, 7);
+    let strict_posts = |id: i32| {
+        db.query::<Post>().deserialize_mode(DeserializeMode::Strict).where_eq("id", id).fetch::<Post>()
+    };
+    assert_eq!(strict_posts(1).unwrap()[0].author_id, 7);
+    let err = strict_posts(2).unwrap_err().to_string();
+    assert!(err.contains("invalid type: string \"+7\", expected i32 in column `author_id` of row with id = 2"), "{}", err);
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct Cents(i64);
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct Measurement {
+    id: i32,
+    counter: u64,
+    ratio: f64,
+    ratio_text: f64,
+    active: bool,
+    price: Cents,
+    labels: Vec<String>,
+}
+
+impl Model for Measurement {
+    fn table_name() -> &'static str {
+        "measurements"
+    }
+
+    fn fields() -> &'static [&'static str] {
+        &["id", "counter", "ratio", "ratio_text", "active", "price", "labels"]
+    }
+
+    fn column_type(field: &str) -> crate::ColumnType {
+        match field {
+            "active" | "price" => crate::ColumnType::Integer,
+            "ratio" => crate::ColumnType::Real,
+            _ => crate::ColumnType::Text,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct Counter {
+    id: i32,
+    value: u64,
+}
+
+impl Model for Counter {
+    fn table_name() -> &'static str {
+        "counters"
+    }
+
+    fn fields() -> &'static [&'static str] {
+        &["id", "value"]
+    }
+
+    fn column_type(_field: &str) -> crate::ColumnType {
+        crate::ColumnType::Integer
+    }
+}
+
+#[test]
+fn test_typed_value_binding() {
+    let db = Database::connect_in_memory().unwrap();
+    db.create_table::<Measurement>().unwrap();
+
+    let mut measurement = Measurement {
+        id: 1,
+        counter: u64::MAX,
+        ratio: 0.1 + 0.2,
+        ratio_text: 0.1 + 0.2,
+        active: true,
+        price: Cents(1999),
+        labels: vec!["a".to_string(), "b".to_string()],
+    };
+    db.insert(&mut measurement).unwrap();
+
+    // Values keep their SQLite storage classes instead of all being bound as text
+    let types: (String, String, String, String, String) = db.conn
+        .query_row(
+            "SELECT typeof(counter), typeof(ratio), typeof(active), typeof(price), labels FROM measurements",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?)),
+        )
+        .unwrap();
+    assert_eq!(
+        types,
+        ("text".into(), "real".into(), "integer".into(), "integer".into(), r#"["a","b"]"#.into())
+    );
+
+    // u64 above i64::MAX, floats and nested values round-trip exactly
+    assert_eq!(db.find_by_id::<Measurement>(1).unwrap(), Some(measurement));
+
+    let strict = db.query::<Measurement>()
+        .deserialize_mode(crate::DeserializeMode::Strict)
+        .fetch_one::<Measurement>()
+        .unwrap()
+        .unwrap();
+    assert_eq!(strict.counter, u64::MAX);
+    assert!(strict.active);
+
+    // Bools are compared as the 0/1 they are stored as
+    assert_eq!(db.query::<Measurement>().where_eq("active", true).fetch::<Measurement>().unwrap().len(), 1);
+    assert!(db.query::<Measurement>().where_eq("active", false).fetch::<Measurement>().unwrap().is_empty());
+    db.query::<Measurement>().set("active", false).update::<Measurement>().unwrap();
+    assert!(!db.find_by_id::<Measurement>(1).unwrap().unwrap().active);
+
+    // Bools in the default TEXT column are stored and bound as "true"/"false"
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Flag {
+        id: i32,
+        active: bool,
+    }
+
+    impl Model for Flag {
+        fn table_name() -> &'static str { "flags" }
+        fn fields() -> &'static [&'static str] { &["id", "active"] }
+    }
+
+    db.create_table::<Flag>().unwrap();
+    db.insert(&mut Flag { id: 1, active: true }).unwrap();
+    db.insert(&mut Flag { id: 2, active: false }).unwrap();
+    let stored: String = db.conn.query_row("SELECT active FROM flags WHERE id = 1", [], |row| row.get(0)).unwrap();
+    assert_eq!(stored, "true");
+    let active = db.query::<Flag>().where_eq("active", true).fetch::<Flag>().unwrap();
+    assert_eq!(active, vec![Flag { id: 1, active: true }]);
+    db.query::<Flag>().where_eq("active", false).set("active", true).update::<Flag>().unwrap();
+    assert_eq!(db.query::<Flag>().where_eq("active", true).fetch::<Flag>().unwrap().len(), 2);
+
+    // Wide integers cannot be stored losslessly in INTEGER columns
+    db.create_table::<Counter>().unwrap();
+    db.insert(&mut Counter { id: 1, value: i64::MAX as u64 }).unwrap();
+    let err = db.insert(&mut Counter { id: 2, value: u64::MAX }).unwrap_err().to_string();
+    assert!(err.contains("18446744073709551615 does not fit in INTEGER column `value`"), "{}", err);
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct LineItem {
+    id: i32,
+    amount: String,
+    rate: f64,
+    serial: i128,
+}
+
+impl Model for LineItem {
+    fn table_name() -> &'static str {
+        "line_items"
+    }
+
+    fn fields() -> &'static [&'static str] {
+        &["id", "amount", "rate", "serial"]
+    }
+
+    fn column_type(field: &str) -> crate::ColumnType {
+        match field {
+            "amount" => crate::ColumnType::Decimal(2),
+            "rate" => crate::ColumnType::Decimal(4),
+            _ => crate::ColumnType::Text,
+        }
+    }
+}
+
+fn line_item(id: i32, amount: &str) -> LineItem {
+    LineItem { id, amount: amount.to_string(), rate: 0.0825, serial: i128::MIN + id as i128 }
+}
+
+#[test]
+fn test_decimal_columns() {
+    use crate::decimal::{from_scaled, to_scaled};
+
+    assert_eq!(to_scaled("-12.5", 2), Some(-1250));
+    assert_eq!(to_scaled("0.100", 1), Some(1));
+    assert_eq!(to_scaled("0.125", 2), None);
+    assert_eq!(to_scaled("1.2.3", 2), None);
+    assert_eq!(to_scaled("92233720368547758.08", 2), None);
+    assert_eq!(from_scaled(1999, 2), "19.99");
+    assert_eq!(from_scaled(-7, 3), "-0.007");
+    assert_eq!(from_scaled(i64::MIN, 0), i64::MIN.to_string());
+
+    let db = Database::connect_in_memory().unwrap();
+    db.create_table::<LineItem>().unwrap();
+    for (id, amount) in [(1, "100.00"), (2, "9.50"), (3, "19.99")] {
+        db.insert(&mut line_item(id, amount)).unwrap();
+    }
+
+    // Stored as scaled integers, so SQL arithmetic stays exact
+    let total: i64 = db.conn.query_row("SELECT SUM(amount) FROM line_items", [], |row| row.get(0)).unwrap();
+    assert_eq!(total, 12949);
+
+    // Comparisons and ordering are numeric rather than textual
+    let over_ten = db.query::<LineItem>()
+        .where_gt("amount", "10")
+        .order_by("amount", true)
+        .fetch::<LineItem>()
+        .unwrap();
+    assert_eq!(over_ten.iter().map(|i| i.amount.as_str()).collect::<Vec<_>>(), vec!["19.99", "100.00"]);
+
+    // Wide integers and decimal-backed floats round-trip exactly
+    assert_eq!(db.find_by_id::<LineItem>(2).unwrap(), Some(line_item(2, "9.50")));
+
+    db.query::<LineItem>().where_eq("id", 2).set("amount", "12.3").update::<LineItem>().unwrap();
+    assert_eq!(db.find_by_id::<LineItem>(2).unwrap().unwrap().amount, "12.30");
+
+    let err = db.insert(&mut line_item(4, "0.125")).unwrap_err().to_string();
+    assert!(err.contains("\"0.125\" does not fit decimal column `amount` with 2 decimal places"), "{}", err);
+}
+
+#[cfg(feature = "rust_decimal")]
+#[test]
+fn test_rust_decimal_columns() {
+    use crate::decimal::Decimal;
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Payment {
+        id: i32,
+        amount: Decimal,
+    }
+
+    impl Model for Payment {
+        fn table_name() -> &'static str {
+            "payments"
+        }
+
+        fn fields() -> &'static [&'static str] {
+            &["id", "amount"]
+        }
+
+        fn column_type(field: &str) -> crate::ColumnType {
+            match field {
+                "amount" => crate::ColumnType::Decimal(2),
+                _ => crate::ColumnType::Text,
+            }
+        }
+    }
+
+    let db = Database::connect_in_memory().unwrap();
+    db.create_table::<Payment>().unwrap();
+
+    let amounts = [Decimal::new(1999, 2), Decimal::new(-5, 1), Decimal::new(100_000_000_001, 2)];
+    for (id, amount) in amounts.iter().enumerate() {
+        db.insert(&mut Payment { id: id as i32 + 1, amount: *amount }).unwrap();
+    }
+
+    let payments = db.select_all::<Payment>().unwrap();
+    assert_eq!(payments.iter().map(|p| p.amount).collect::<Vec<_>>(), amounts);
+
+    let small = db.query::<Payment>().where_lt("amount", Decimal::new(20, 0)).fetch::<Payment>().unwrap();
+    assert_eq!(small.iter().map(|p| p.id).collect::<Vec<_>>(), vec![1, 2]);
+
+    let err = db.insert(&mut Payment { id: 4, amount: Decimal::new(1, 3) }).unwrap_err().to_string();
+    assert!(err.contains("does not fit decimal column `amount`"), "{}", err);
+}
+
+#[test]
+fn test_database_options() {
+    use crate::{DatabaseOptions, JournalMode, Synchronous};
+    use std::time::Duration;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("options.db");
+
+    let options = DatabaseOptions::new()
+        .journal_mode(JournalMode::Wal)
+        .synchronous(Synchronous::Normal)
+        .busy_timeout(Duration::from_millis(250))
+        .foreign_keys(true)
+        .cache_size(-4096)
+        .mmap_size(1 << 20)
+        .pragma("user_version", 7)
+        .pragma("application_id", 0x5042);
+    let db = Database::connect_with_options(&path, &options).unwrap();
+
+    let pragma = |name: &str| -> String {
+        db.conn
+            .query_row(&format!("PRAGMA {}", name), [], |row| row.get::<_, rusqlite::types::Value>(0))
+            .map(|value| match value {
+                rusqlite::types::Value::Integer(i) => i.to_string(),
+                rusqlite::types::Value::Text(s) => s,
+                other => format!("{:?}", other),
+            })
+            .unwrap()
+    };
+    assert_eq!(pragma("journal_mode"), "wal");
+    assert_eq!(pragma("synchronous"), "1");
+    assert_eq!(pragma("busy_timeout"), "250");
+    assert_eq!(pragma("foreign_keys"), "1");
+    assert_eq!(pragma("cache_size"), "-4096");
+    assert_eq!(pragma("user_version"), "7");
+    assert_eq!(pragma("application_id"), "20546");
+
+    db.create_table::<User>().unwrap();
+    db.insert(&mut User { id: 1, name: "Alice".into(), email: "alice@example.com".into() }).unwrap();
+
+    // Read-only connections can load rows but not write them
+    let reader = Database::connect_with_options(&path, &DatabaseOptions::new().read_only(true)).unwrap();
+    assert_eq!(reader.select_all::<User>().unwrap().len(), 1);
+    assert!(reader.insert(&mut User { id: 2, name: "Bob".into(), email: "bob@example.com".into() }).is_err());
+
+    // Without `create`, missing files are an error
+    let missing = dir.path().join("missing.db");
+    assert!(Database::connect_with_options(&missing, &DatabaseOptions::new().create(false)).is_err());
+    assert!(!missing.exists());
+
+    // Pragma names are not interpolated unchecked
+    let bad = DatabaseOptions::new().pragma("user_version; DROP TABLE users", 1);
+    assert!(Database::connect_with_options(&path, &bad).is_err());
+}
+
+#[test]
+fn test_connection_pool() {
+    use crate::{DatabaseOptions, JournalMode, Pool};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    let dir = tempfile::tempdir().unwrap();
+    let connects = Arc::new(AtomicUsize::new(0));
+    let counter = Arc::clone(&connects);
+    let pool = Pool::builder(dir.path().join("pool.db"))
+        .options(DatabaseOptions::new().journal_mode(JournalMode::Wal).busy_timeout(Duration::from_secs(5)))
+        .on_connect(move |conn| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            conn.pragma_update(None, "temp_store", "MEMORY")
+        })
+        .max_size(3)
+        .checkout_timeout(Duration::from_millis(50))
+        .single_writer(true)
+        .build()
+        .unwrap();
+
+    pool.writer().unwrap().create_table::<User>().unwrap();
+
+    // Writers take turns on one connection while readers run alongside them
+    let handles: Vec<_> = (1..=8)
+        .map(|id| {
+            let pool = pool.clone();
+            std::thread::spawn(move || {
+                let name = format!("user{}", id);
+                let email = format!("{}@example.com", name);
+                pool.writer().unwrap().insert(&mut User { id, name, email }).unwrap();
+                pool.get().unwrap().select_all::<User>().unwrap().len()
+            })
+        })
+        .collect();
+    for handle in handles {
+        assert!(handle.join().unwrap() >= 1);
+    }
+    assert_eq!(pool.get().unwrap().select_all::<User>().unwrap().len(), 8);
+    assert!(pool.size() <= 4);
+    assert_eq!(connects.load(Ordering::SeqCst), pool.size());
+
+    // Readers are read-only in single-writer mode
+    let reader = pool.get().unwrap();
+    assert!(reader.insert(&mut User { id: 9, name: "x".into(), email: "x".into() }).is_err());
+    drop(reader);
+
+    // Checkouts time out once every connection is in use
+    let held: Vec<_> = (0..3).map(|_| pool.get().unwrap()).collect();
+    let err = pool.get().err().unwrap();
+    assert!(err.to_string().contains("timed out waiting for a pooled connection"), "{}", err);
+    drop(held);
+    assert!(pool.get().is_ok());
+    assert_eq!(pool.idle(), pool.size());
+
+    // Transactions left open are rolled back before the connection is reused
+    let writer = pool.writer().unwrap();
+    writer.raw_execute("BEGIN", []).unwrap();
+    writer.insert(&mut User { id: 10, name: "x".into(), email: "x".into() }).unwrap();
+    drop(writer);
+    let writer = pool.writer().unwrap();
+    assert!(writer.find_by_id::<User>(10).unwrap().is_none());
+    writer.raw_execute("BEGIN", []).unwrap();
+    writer.raw_execute("COMMIT", []).unwrap();
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn test_async_database() {
+    use crate::AsyncDatabase;
+
+    let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+    runtime.block_on(async {
+        let db = AsyncDatabase::connect_in_memory().await.unwrap();
+        db.create_table::<User>().await.unwrap();
+
+        // Futures are Send, so they can run on spawned tasks
+        let tasks: Vec<_> = (1..=3)
+            .map(|id| {
+                let db = db.clone();
+                tokio::spawn(async move {
+                    let name = format!("user{}", id);
+                    let email = format!("{}@example.com", name);
+                    db.insert(User { id, name, email }).await
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap().unwrap();
+        }
+
+        assert_eq!(db.select_all::<User>().await.unwrap().len(), 3);
+
+        let mut user = db.find_by_id::<User>(2).await.unwrap().unwrap();
+        user.name = "Bob".to_string();
+        let (user, changed) = db.update(user).await.unwrap();
+        assert_eq!((user.name.as_str(), changed), ("Bob", 1));
+
+        // Models come back with changes made during the write, so versioned rows can be updated again
+        db.create_table::<Document>().await.unwrap();
+        let (mut doc, _) = db.insert(Document { id: 1, title: "Draft".to_string(), lock_version: 0 }).await.unwrap();
+        for title in ["First", "Second"] {
+            doc.title = title.to_string();
+            doc = db.update(doc).await.unwrap().0;
+        }
+        assert_eq!(doc.lock_version, 2);
+        assert_eq!(db.find_by_id::<Document>(1).await.unwrap().unwrap(), doc);
+
+        let bobs = db.fetch::<User, _>(|q| q.where_eq("name", "Bob")).await.unwrap();
+        assert_eq!(bobs.iter().map(|u| u.id).collect::<Vec<_>>(), vec![2]);
+
+        assert_eq!(db.delete::<User>(1).await.unwrap(), 1);
+        let count = db.call(|db| db.select_all::<User>().map(|users| users.len())).await.unwrap();
+        assert_eq!(count, 2);
+
+        // A panicking call fails on its own without taking the worker down
+        let panicked = db.call::<(), _>(|_| panic!("boom")).await;
+        assert!(panicked.is_err());
+        assert_eq!(db.select_all::<User>().await.unwrap().len(), 2);
+
+        assert!(AsyncDatabase::connect("/nonexistent/dir/pebble.db").await.is_err());
+    });
+}
+
+#[test]
+fn test_statement_cache() {
+    use crate::StatementCacheStats;
+
+    let db = Database::connect_in_memory().unwrap();
+    db.create_table::<User>().unwrap();
+    assert_eq!(db.statement_cache_stats(), StatementCacheStats { hits: 0, misses: 0, capacity: 16 });
+
+    for id in 1..=5 {
+        let name = format!("user{}", id);
+        db.insert(&mut User { id, name: name.clone(), email: name }).unwrap();
+    }
+    for id in 1..=5 {
+        db.find_by_id::<User>(id).unwrap();
+    }
+    let stats = db.statement_cache_stats();
+    assert_eq!((stats.hits, stats.misses), (8, 2));
+
+    // Query builder SQL is cached per distinct statement shape
+    db.reset_statement_cache_stats();
+    for name in ["user1", "user2", "user3"] {
+        db.query::<User>().where_eq("name", name).fetch::<User>().unwrap();
+    }
+    let stats = db.statement_cache_stats();
+    assert_eq!((stats.hits, stats.misses), (2, 1));
+
+    // With room for one statement, alternating statements always miss
+    let db = db.with_statement_cache_capacity(1);
+    db.reset_statement_cache_stats();
+    for id in 1..=3 {
+        db.find_by_id::<User>(id).unwrap();
+        db.select_all::<User>().unwrap();
+    }
+    let stats = db.statement_cache_stats();
+    assert_eq!((stats.hits, stats.misses, stats.capacity), (0, 6, 1));
+
+    db.clear_statement_cache();
+    db.reset_statement_cache_stats();
+    db.select_all::<User>().unwrap();
+    assert_eq!(db.statement_cache_stats().misses, 1);
+
+    // Capacity can also come from connection options
+    let options = crate::DatabaseOptions::new().statement_cache_capacity(64);
+    let db = Database::connect_with_options(":memory:", &options).unwrap();
+    assert_eq!(db.statement_cache_stats().capacity, 64);
+}
+
+#[test]
+fn test_streaming_results() {
+    let db = Database::connect_in_memory().unwrap();
+    db.create_table::<User>().unwrap();
+    for id in 1..=10 {
+        let name = format!("user{}", id);
+        db.insert(&mut User { id, name: name.clone(), email: name }).unwrap();
+    }
+
+    let mut stream = db.select_iter::<User>().unwrap();
+    let mut seen = 0;
+    for user in stream.iter().unwrap() {
+        seen += 1;
+        assert_eq!(user.unwrap().id, seen);
+    }
+    assert_eq!(seen, 10);
+
+    // Iteration can stop early and be restarted
+    assert_eq!(stream.iter().unwrap().take(2).count(), 2);
+
+    let mut stream = db.query::<User>()
+        .where_gt("id", 3)
+        .order_by("id", false)
+        .fetch_iter::<User>()
+        .unwrap();
+    let batches: Vec<Vec<i32>> = stream.chunks(3).unwrap()
+        .map(|batch| batch.unwrap().into_iter().map(|u| u.id).collect())
+        .collect();
+    assert_eq!(batches, vec![vec![10, 9, 8], vec![7, 6, 5], vec![4]]);
+
+    // Rows that fail to load are reported without ending the stream
+    db.conn.execute("UPDATE users SET name = NULL WHERE id = 2", []).unwrap();
+    let results: Vec<_> = db.select_iter::<User>().unwrap().iter().unwrap().collect();
+    assert_eq!(results.len(), 10);
+    assert_eq!(results.iter().filter(|r| r.is_err()).count(), 1);
+}
+
+#[test]
+fn test_query_logging() {
+    use crate::logging::QueryEvent;
+    use std::sync::{Arc, Mutex};
+
+    type Logged = (String, Vec<String>, usize);
+    let events: Arc<Mutex<Vec<Logged>>> = Arc::new(Mutex::new(Vec::new()));
+    let sink = Arc::clone(&events);
+    let db = Database::connect_in_memory()
+        .unwrap()
+        .with_redacted_fields(&["email"])
+        .with_query_logger(move |event: &QueryEvent<'_>| {
+            sink.lock().unwrap().push((event.sql.to_string(), event.params.to_vec(), event.rows));
+        });
+
+    db.create_table::<User>().unwrap();
+    db.insert(&mut User { id: 1, name: "O'Brien".to_string(), email: "ob@example.com".to_string() }).unwrap();
+    db.insert(&mut User { id: 2, name: "Bob".to_string(), email: "bob@example.com".to_string() }).unwrap();
+    db.find_by_id::<User>(1).unwrap();
+    db.query::<User>().where_eq("email", "bob@example.com").fetch::<User>().unwrap();
+    db.query::<User>().set("name", "Robert").where_gt("id", 1).update::<User>().unwrap();
+
+    let events = events.lock().unwrap();
+    assert_eq!(events.len(), 6);
+    assert!(events[0].0.starts_with("CREATE TABLE"));
+    assert_eq!(events[1].0, r#"INSERT INTO "users" ("id", "name", "email") VALUES (?, ?, ?)"#);
+    assert_eq!(events[1].1, vec!["1", "'O''Brien'", "'[REDACTED]'"]);
+    assert_eq!(events[1].2, 1);
+    assert_eq!(events[3].0, r#"SELECT "id", "name", "email" FROM "users" WHERE "id" = ?"#);
+    assert_eq!((events[3].1.clone(), events[3].2), (vec!["1".to_string()], 1));
+    assert_eq!(events[4].0, r#"SELECT "id", "name", "email" FROM "users" WHERE "email" = ?"#);
+    assert_eq!((events[4].1.clone(), events[4].2), (vec!["'[REDACTED]'".to_string()], 1));
+    assert_eq!(events[5].0, r#"UPDATE "users" SET "name" = ? WHERE "id" > ?"#);
+    assert_eq!((events[5].1.clone(), events[5].2), (vec!["'Robert'".to_string(), "'1'".to_string()], 1));
+}
+
+#[test]
+fn test_query_inspection() {
+    use rusqlite::types::Value;
+
+    let db = Database::connect_in_memory().unwrap();
+    db.create_table::<User>().unwrap();
+
+    let query = db.query::<User>()
+        .where_eq("name", "Alice")
+        .where_gt("id", 3)
+        .order_by("id", false)
+        .limit(5);
+    let (sql, params) = query.to_sql().unwrap();
+    assert_eq!(
+        sql,
+        r#"SELECT "id", "name", "email" FROM "users" WHERE "name" = ? AND "id" > ? ORDER BY "id" DESC LIMIT 5"#
+    );
+    assert_eq!(params, vec![Value::Text("Alice".to_string()), Value::Text("3".to_string())]);
+
+    // Inspecting does not consume the builder
+    assert!(query.fetch::<User>().unwrap().is_empty());
+
+    let plan = db.query::<User>().where_eq("email", "a@example.com").explain().unwrap();
+    assert!(plan.has_full_scan());
+    assert_eq!(plan.full_scans().len(), 1);
+    assert!(plan.to_string().starts_with("QUERY PLAN\n`--SCAN"));
+
+    db.conn.execute("CREATE INDEX idx_users_email ON users (email)", []).unwrap();
+    let plan = db.query::<User>().where_eq("email", "a@example.com").explain().unwrap();
+    assert!(!plan.has_full_scan());
+    assert!(plan.nodes[0].detail.contains("idx_users_email"));
+
+    let plan = db.query::<User>().where_eq("id", 1).explain().unwrap();
+    assert!(!plan.has_full_scan());
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct Keyword {
+    id: i32,
+    order: i32,
+    group: String,
+}
+
+impl Model for Keyword {
+    fn table_name() -> &'static str {
+        "select"
+    }
+
+    fn fields() -> &'static [&'static str] {
+        &["id", "order", "group"]
+    }
+
+    fn column_type(field: &str) -> crate::ColumnType {
+        match field {
+            "order" => crate::ColumnType::Integer,
+            _ => crate::ColumnType::Text,
+        }
+    }
+}
+
+#[test]
+fn test_identifier_quoting() {
+    let db = Database::connect_in_memory().unwrap();
+    db.create_table::<Keyword>().unwrap();
+
+    // Keyword table and column names work through every operation
+    let id = db.insert(&mut Keyword { id: 1, order: 2, group: "a".to_string() }).unwrap();
+    db.insert(&mut Keyword { id: 2, order: 1, group: "b".to_string() }).unwrap();
+    db.update(&mut Keyword { id: 1, order: 3, group: "a".to_string() }).unwrap();
+    assert_eq!(db.find_by_id::<Keyword>(id).unwrap().unwrap().order, 3);
+
+    let tickets = db.query::<Keyword>().where_eq("group", "b").order_by("order", true).fetch::<Keyword>().unwrap();
+    assert_eq!(tickets, vec![Keyword { id: 2, order: 1, group: "b".to_string() }]);
+    assert_eq!(db.query::<Keyword>().set("group", "c").where_gt("order", 2).update::<Keyword>().unwrap(), 1);
+    assert_eq!(db.select_all::<Keyword>().unwrap().len(), 2);
+    db.delete::<Keyword>(2).unwrap();
+    db.drop_table::<Keyword>().unwrap();
+
+    // Unknown columns are rejected before any SQL runs
+    db.create_table::<User>().unwrap();
+    db.insert(&mut User { id: 1, name: "Alice".to_string(), email: "alice@example.com".to_string() }).unwrap();
+
+    let injected = db.query::<User>().order_by("name; DROP TABLE users; --", true).fetch::<User>();
+    assert!(matches!(injected, Err(rusqlite::Error::InvalidColumnName(ref c)) if c == "name; DROP TABLE users; --"));
+    assert!(matches!(
+        db.query::<User>().where_eq("missing", 1).to_sql(),
+        Err(rusqlite::Error::InvalidColumnName(ref c)) if c == "missing"
+    ));
+    assert!(matches!(
+        db.query::<User>().set("missing", 1).update::<User>(),
+        Err(rusqlite::Error::InvalidColumnName(_))
+    ));
+    assert!(matches!(db.reserve_blob::<User>("missing", 1, 4), Err(rusqlite::Error::InvalidColumnName(_))));
+    assert_eq!(db.select_all::<User>().unwrap().len(), 1);
+}
+
+#[test]
+fn test_raw_queries() {
+    use rusqlite::named_params;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct AuthorStats {
+        name: String,
+        posts: i64,
+    }
+
+    let db = Database::connect_in_memory().unwrap();
+    db.create_table::<User>().unwrap();
+    db.create_table::<Post>().unwrap();
+    db.insert(&mut User { id: 1, name: "Alice".to_string(), email: "alice@example.com".to_string() }).unwrap();
+    db.insert(&mut User { id: 2, name: "Bob".to_string(), email: "bob@example.com".to_string() }).unwrap();
+    for id in 1..=3 {
+        let author_id = if id == 3 { 2 } else { 1 };
+        db.insert(&mut Post { id, title: format!("post{}", id), content: String::new(), author_id }).unwrap();
+    }
+
+    // Columns are matched by name, whatever order they are selected in
+    let users: Vec<User> = db.raw_query("SELECT email, id, name FROM users WHERE id = ?", [2]).unwrap();
+    assert_eq!(users, vec![User { id: 2, name: "Bob".to_string(), email: "bob@example.com".to_string() }]);
+
+    // Extra columns from SELECT * and joins are ignored
+    let users: Vec<User> = db.raw_query(
+        "SELECT u.*, p.title FROM users u JOIN posts p ON p.author_id = u.id WHERE p.title = :title",
+        named_params! { ":title": "post3" },
+    ).unwrap();
+    assert_eq!(users[0].name, "Bob");
+
+    let stats: Vec<AuthorStats> = db.raw_query_as(
+        "SELECT COUNT(p.id) AS posts, u.name FROM users u JOIN posts p ON p.author_id = u.id GROUP BY u.id ORDER BY u.id",
+        [],
+    ).unwrap();
+    assert_eq!(stats, vec![
+        AuthorStats { name: "Alice".to_string(), posts: 2 },
+        AuthorStats { name: "Bob".to_string(), posts: 1 },
+    ]);
+
+    let changed = db.raw_execute("UPDATE posts SET title = upper(title) WHERE author_id = ?1", [1]).unwrap();
+    assert_eq!(changed, 2);
+    assert_eq!(db.find_by_id::<Post>(1).unwrap().unwrap().title, "POST1");
+
+    // A missing field is reported rather than silently defaulted
+    assert!(db.raw_query::<User>("SELECT id, name FROM users", []).is_err());
+}
+
+#[test]
+fn test_row_mapping_by_column_name() {
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Note {
+        id: i32,
+        title: String,
+        body: Option<String>,
+        #[serde(default)]
+        views: i64,
+    }
+
+    impl Model for Note {
+        fn table_name() -> &'static str {
+            "notes"
+        }
+
+        fn fields() -> &'static [&'static str] {
+            &["id", "title", "body", "views"]
+        }
+    }
+
+    // The table predates `views`, which falls back to its serde default
+    let db = Database::connect_in_memory().unwrap();
+    db.conn.execute(
+        "CREATE TABLE notes (body TEXT, extra TEXT, title TEXT, id INTEGER PRIMARY KEY)",
+        [],
+    ).unwrap();
+    db.conn.execute("INSERT INTO notes VALUES ('hello', 'ignored', 'first', 1)", []).unwrap();
+
+    let expected = Note { id: 1, title: "first".to_string(), body: Some("hello".to_string()), views: 0 };
+    assert_eq!(db.raw_query::<Note>("SELECT * FROM notes", []).unwrap(), vec![expected]);
+
+    // Optional fields may be left out of the selection; required ones are an error naming the field
+    let partial = db.raw_query::<Note>("SELECT id, title FROM notes", []).unwrap();
+    assert_eq!(partial, vec![Note { id: 1, title: "first".to_string(), body: None, views: 0 }]);
+    let err = db.raw_query::<Note>("SELECT id, body FROM notes", []).unwrap_err();
+    assert!(matches!(err, rusqlite::Error::InvalidColumnName(ref c) if c == "title"), "{:?}", err);
+
+    // Conversion errors name the right column even when columns are reordered
+    db.conn.execute("UPDATE notes SET title = NULL", []).unwrap();
+    let err = db.raw_query::<Note>("SELECT body, title, id FROM notes", []).unwrap_err();
+    match err {
+        rusqlite::Error::FromSqlConversionFailure(idx, _, e) => {
+            assert_eq!(idx, 1);
+            assert!(e.to_string().contains("title"), "{}", e);
+        }
+        other => panic!("unexpected error: {:?}", other),
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct Account {
+    id: i32,
+    email: String,
+    version: i64,
+    #[serde(skip)]
+    loaded: bool,
+    #[serde(skip)]
+    saved: bool,
+}
+
+impl Model for Account {
+    fn table_name() -> &'static str {
+        "accounts"
+    }
+
+    fn fields() -> &'static [&'static str] {
+        &["id", "email", "version"]
+    }
+
+    fn column_type(field: &str) -> crate::ColumnType {
+        match field {
+            "version" => crate::ColumnType::Integer,
+            _ => crate::ColumnType::Text,
+        }
+    }
+
+    fn before_insert(&mut self) -> crate::Result<()> {
+        if !self.email.contains('@') {
+            return Err(rusqlite::Error::InvalidQuery);
+        }
+        self.email = self.email.trim().to_lowercase();
+        Ok(())
+    }
+
+    fn after_insert(&mut self) {
+        self.saved = true;
+    }
+
+    fn before_update(&mut self) -> crate::Result<()> {
+        self.version += 1;
+        Ok(())
+    }
+
+    fn after_update(&mut self) {
+        self.saved = true;
+    }
+
+    fn before_delete(id: i64) -> crate::Result<()> {
+        if id == 1 {
+            return Err(rusqlite::Error::InvalidQuery);
+        }
+        Ok(())
+    }
+
+    fn after_load(&mut self) {
+        self.loaded = true;
+    }
+}
+
+#[test]
+fn test_lifecycle_hooks() {
+    let account = |id, email: &str| Account { id, email: email.to_string(), version: 0, loaded: false, saved: false };
+
+    let db = Database::connect_in_memory().unwrap();
+    db.create_table::<Account>().unwrap();
+
+    let mut alice = account(1, "  Alice@Example.COM ");
+    db.insert(&mut alice).unwrap();
+    assert_eq!(alice.email, "alice@example.com");
+    assert!(alice.saved);
+
+    // A failing before hook aborts the write
+    assert!(db.insert(&mut account(2, "not an email")).is_err());
+    assert_eq!(db.select_all::<Account>().unwrap().len(), 1);
+
+    let mut loaded = db.find_by_id::<Account>(1).unwrap().unwrap();
+    assert!(loaded.loaded);
+    assert_eq!(loaded.email, "alice@example.com");
+
+    loaded.saved = false;
+    db.update(&mut loaded).unwrap();
+    db.update(&mut loaded).unwrap();
+    assert_eq!(loaded.version, 2);
+    assert!(loaded.saved);
+    let fetched = db.query::<Account>().fetch::<Account>().unwrap();
+    assert!(fetched[0].loaded);
+    assert_eq!(fetched[0].version, 2);
+
+    assert!(db.delete::<Account>(1).is_err());
+    assert!(db.force_delete::<Account>(1).is_err());
+    assert_eq!(db.select_all::<Account>().unwrap().len(), 1);
+
+    db.insert(&mut account(3, "bob@example.com")).unwrap();
+    assert_eq!(db.delete::<Account>(3).unwrap(), 1);
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct Registration {
+    id: i32,
+    username: String,
+    email: Option<String>,
+    age: i32,
+    balance: String,
+    starts: i64,
+    ends: i64,
+}
+
+impl Model for Registration {
+    fn table_name() -> &'static str {
+        "registrations"
+    }
+
+    fn fields() -> &'static [&'static str] {
+        &["id", "username", "email", "age", "balance", "starts", "ends"]
+    }
+
+    fn column_type(field: &str) -> crate::ColumnType {
+        match field {
+            "age" | "starts" | "ends" => crate::ColumnType::Integer,
+            "balance" => crate::ColumnType::Decimal(2),
+            _ => crate::ColumnType::Text,
+        }
+    }
+
+    fn before_insert(&mut self) -> crate::Result<()> {
+        self.username = self.username.trim().to_string();
+        Ok(())
+    }
+
+    fn validate(&self, v: &mut crate::validate::Validator<'_>) {
+        v.required("username").length("username", 3..=12);
+        v.range("age", 18, 120);
+        v.range("balance", 0, 1000);
+        v.custom("email", |value| match value {
+            rusqlite::types::Value::Text(s) if !s.contains('@') => Err("must be an email address".to_string()),
+            _ => Ok(()),
+        });
+        if self.ends < self.starts {
+            v.add_error("ends", "must not be before starts");
+        }
+        #[cfg(feature = "regex")]
+        {
+            let pattern = regex::Regex::new("^[a-z0-9_]+$").unwrap();
+            v.regex("username", &pattern);
+        }
+    }
+}
+
+#[test]
+fn test_validation() {
+    use crate::validate::{FieldError, ValidationErrors};
+
+    let registration = |username: &str, email: Option<&str>, age, balance: &str| Registration {
+        id: 1,
+        username: username.to_string(),
+        email: email.map(str::to_string),
+        age,
+        balance: balance.to_string(),
+        starts: 1,
+        ends: 2,
+    };
+
+    let db = Database::connect_in_memory().unwrap();
+    db.create_table::<Registration>().unwrap();
+
+    // Hooks run first, so trimmed values are validated
+    let mut valid = registration("  alice_1 ", None, 30, "999.99");
+    db.insert(&mut valid).unwrap();
+
+    // Every failing rule is reported, nothing is written
+    let mut invalid = registration("  ", Some("nope"), 12, "1000.01");
+    invalid.id = 2;
+    invalid.ends = 0;
+    let err = db.insert(&mut invalid).unwrap_err();
+    let errors = ValidationErrors::from_error(&err).unwrap();
+    #[cfg(not(feature = "regex"))]
+    assert_eq!(errors.fields(), vec!["username", "username", "age", "balance", "email", "ends"]);
+    #[cfg(feature = "regex")]
+    assert_eq!(errors.fields(), vec!["username", "username", "age", "balance", "email", "ends", "username"]);
+    assert_eq!(errors.errors[1], FieldError { field: "username".to_string(), message: "length must be between 3 and 12".to_string() });
+    assert!(err.to_string().contains("age must be between 18 and 120"), "{}", err);
+    assert_eq!(db.select_all::<Registration>().unwrap().len(), 1);
+
+    // Updates are validated too
+    valid.age = 200;
+    let err = db.update(&mut valid).unwrap_err();
+    assert_eq!(ValidationErrors::from_error(&err).unwrap().fields(), vec!["age"]);
+    assert_eq!(db.find_by_id::<Registration>(1).unwrap().unwrap().age, 30);
+
+    // Other errors are not validation errors
+    assert!(ValidationErrors::from_error(&rusqlite::Error::InvalidQuery).is_none());
+
+    #[cfg(feature = "regex")]
+    {
+        let err = db.insert(&mut registration("Bad Name", None, 30, "1")).unwrap_err();
+        assert_eq!(ValidationErrors::from_error(&err).unwrap().fields(), vec!["username"]);
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+struct Document {
+    id: i32,
+    title: String,
+    lock_version: i64,
+}
+
+impl Model for Document {
+    fn table_name() -> &'static str {
+        "documents"
+    }
+
+    fn fields() -> &'static [&'static str] {
+        &["id", "title", "lock_version"]
+    }
+
+    fn column_type(field: &str) -> crate::ColumnType {
+        match field {
+            "lock_version" => crate::ColumnType::Integer,
+            _ => crate::ColumnType::Text,
+        }
+    }
+
+    fn version_field() -> Option<&'static str> {
+        Some("lock_version")
+    }
+
+    fn set_version(&mut self, version: i64) {
+        self.lock_version = version;
+    }
+}
+
+/// Declares a version field but never stores the new version
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Memo {
+    id: i32,
+    body: String,
+    version: i64,
+}
+
+impl Model for Memo {
+    fn table_name() -> &'static str {
+        "memos"
+    }
+
+    fn fields() -> &'static [&'static str] {
+        &["id", "body", "version"]
+    }
+
+    fn column_type(field: &str) -> crate::ColumnType {
+        match field {
+            "version" => crate::ColumnType::Integer,
+            _ => crate::ColumnType::Text,
+        }
+    }
+
+    fn version_field() -> Option<&'static str> {
+        Some("version")
+    }
+}
+
+#[test]
+fn test_optimistic_locking() {
+    use crate::StaleObject;
+
+    let db = Database::connect_in_memory().unwrap();
+    db.create_table::<Document>().unwrap();
+    db.insert(&mut Document { id: 1, title: "Draft".to_string(), lock_version: 0 }).unwrap();
+
+    let mut first = db.find_by_id::<Document>(1).unwrap().unwrap();
+    let mut second = first.clone();
+
+    first.title = "First".to_string();
+    assert_eq!(db.update(&mut first).unwrap(), 1);
+    assert_eq!(first.lock_version, 1);
+
+    // The second writer loaded version 0 and must not overwrite the first
+    second.title = "Second".to_string();
+    let err = db.update(&mut second).unwrap_err();
+    let stale = StaleObject::from_error(&err).unwrap();
+    assert_eq!((stale.table, stale.version), ("documents", 0));
+    assert_eq!(stale.id, rusqlite::types::Value::Integer(1));
+    assert_eq!(second.lock_version, 0);
+    assert_eq!(db.find_by_id::<Document>(1).unwrap().unwrap(), first);
+
+    // The same instance can keep updating
+    first.title = "First again".to_string();
+    db.update(&mut first).unwrap();
+    assert_eq!(first.lock_version, 2);
+    assert_eq!(db.find_by_id::<Document>(1).unwrap().unwrap(), first);
+
+    // Bulk updates bump the version too
+    db.query::<Document>().set("title", "Bulk").update::<Document>().unwrap();
+    assert!(StaleObject::from_error(&db.update(&mut first).unwrap_err()).is_some());
+    assert_eq!(db.find_by_id::<Document>(1).unwrap().unwrap().lock_version, 3);
+
+    // Deleted rows are stale as well
+    let mut current = db.find_by_id::<Document>(1).unwrap().unwrap();
+    db.delete::<Document>(1).unwrap();
+    assert!(StaleObject::from_error(&db.update(&mut current).unwrap_err()).is_some());
+    assert!(StaleObject::from_error(&rusqlite::Error::InvalidQuery).is_none());
+
+    // Without set_version a model would be stale after its first update, so it is refused
+    db.create_table::<Memo>().unwrap();
+    let mut memo = Memo { id: 1, body: "Hello".to_string(), version: 0 };
+    db.insert(&mut memo).unwrap();
+    memo.body = "Changed".to_string();
+    assert!(matches!(db.update(&mut memo), Err(rusqlite::Error::InvalidQuery)));
+    assert_eq!(db.find_by_id::<Memo>(1).unwrap().unwrap(), Memo { id: 1, body: "Hello".to_string(), version: 0 });
+}
+
+#[test]
+fn test_partial_updates() {
+    use crate::logging::QueryEvent;
+    use crate::Tracked;
+    use std::sync::{Arc, Mutex};
+
+    let statements = Arc::new(Mutex::new(Vec::new()));
+    let sink = Arc::clone(&statements);
+    let db = Database::connect_in_memory()
+        .unwrap()
+        .with_query_logger(move |event: &QueryEvent<'_>| sink.lock().unwrap().push(event.sql.to_string()));
+    db.create_table::<User>().unwrap();
+    db.insert(&mut User { id: 1, name: "Alice".to_string(), email: "alice@example.com".to_string() }).unwrap();
+
+    // Two copies loaded before either is saved
+    let mut renamed = db.find_by_id::<User>(1).unwrap().unwrap();
+    let mut moved = db.find_by_id::<User>(1).unwrap().unwrap();
+    renamed.name = "Alicia".to_string();
+    moved.email = "alicia@example.com".to_string();
+    db.update_fields(&mut renamed, &["name"]).unwrap();
+    db.update_fields(&mut moved, &["email"]).unwrap();
+
+    let user = db.find_by_id::<User>(1).unwrap().unwrap();
+    assert_eq!((user.name.as_str(), user.email.as_str()), ("Alicia", "alicia@example.com"));
+    assert!(matches!(db.update_fields(&mut moved, &["nope"]), Err(rusqlite::Error::InvalidColumnName(_))));
+
+    // Tracked models only write what changed since they were loaded
+    let mut tracked = Tracked::new(user).unwrap();
+    assert!(!tracked.is_changed().unwrap());
+    statements.lock().unwrap().clear();
+    assert_eq!(db.save_changes(&mut tracked).unwrap(), 0);
+    assert!(statements.lock().unwrap().is_empty());
+
+    tracked.email = "a@example.com".to_string();
+    assert_eq!(tracked.changed_fields().unwrap(), vec!["email"]);
+    assert_eq!(db.save_changes(&mut tracked).unwrap(), 1);
+    assert_eq!(statements.lock().unwrap().as_slice(), [r#"UPDATE "users" SET "email" = ? WHERE "id" = ?"#]);
+    assert!(!tracked.is_changed().unwrap());
+    assert_eq!(db.find_by_id::<User>(1).unwrap().unwrap().email, "a@example.com");
+
+    // Versioned models keep optimistic locking through partial updates
+    db.create_table::<Document>().unwrap();
+    db.insert(&mut Document { id: 1, title: "Draft".to_string(), lock_version: 0 }).unwrap();
+    let mut doc = Tracked::new(db.find_by_id::<Document>(1).unwrap().unwrap()).unwrap();
+    let mut other = db.find_by_id::<Document>(1).unwrap().unwrap();
+    doc.title = "Final".to_string();
+    db.save_changes(&mut doc).unwrap();
+    assert_eq!(doc.lock_version, 1);
+    assert!(!doc.is_changed().unwrap());
+    assert!(crate::StaleObject::from_error(&db.update_fields(&mut other, &["title"]).unwrap_err()).is_some());
+    assert_eq!(doc.into_inner().title, "Final");
+
+    // Fields changed by before_update are written along with the tracked changes
+    db.create_table::<Account>().unwrap();
+    db.insert(&mut Account { id: 1, email: "a@example.com".to_string(), version: 0, loaded: false, saved: false })
+        .unwrap();
+    let mut account = Tracked::new(db.find_by_id::<Account>(1).unwrap().unwrap()).unwrap();
+    account.email = "b@example.com".to_string();
+    db.save_changes(&mut account).unwrap();
+    assert_eq!(account.version, 1);
+    assert!(!account.is_changed().unwrap());
+    let stored = db.find_by_id::<Account>(1).unwrap().unwrap();
+    assert_eq!((stored.email.as_str(), stored.version), ("b@example.com", 1));
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Invoice {
+    id: i32,
+    customer: String,
+    total: i64,
+    deleted_at: Option<String>,
+}
+
+impl Model for Invoice {
+    fn table_name() -> &'static str {
+        "invoices"
+    }
+
+    fn fields() -> &'static [&'static str] {
+        &["id", "customer", "total", "deleted_at"]
+    }
+
+    fn column_type(field: &str) -> crate::ColumnType {
+        match field {
+            "total" => crate::ColumnType::Integer,
+            _ => crate::ColumnType::Text,
+        }
+    }
+
+    fn deleted_at_field() -> Option<&'static str> {
+        Some("deleted_at")
+    }
+
+    fn history_table() -> Option<&'static str> {
+        Some("invoice_history")
+    }
+
+    fn validate(&self, v: &mut crate::validate::Validator<'_>) {
+        v.range("total", 0, 1_000_000);
+    }
+}
+
+#[test]
+fn test_audit_history() {
+    use crate::Action;
+    use serde_json::json;
+
+    let db = Database::connect_in_memory().unwrap();
+    db.create_table::<Invoice>().unwrap();
+
+    let mut invoice = Invoice { id: 1, customer: "Acme".to_string(), total: 100, deleted_at: None };
+    assert_eq!(db.insert(&mut invoice).unwrap(), 1);
+    // The returned id is the model's row, not the history row written after it
+    let mut globex = Invoice { id: 42, customer: "Globex".to_string(), total: 50, deleted_at: None };
+    assert_eq!(db.insert(&mut globex).unwrap(), 42);
+
+    db.set_actor(Some("user:7"));
+    invoice.total = 120;
+    db.update(&mut invoice).unwrap();
+
+    // Bulk updates record every row they change, and nothing for unchanged rows
+    db.query::<Invoice>().where_eq("customer", "Acme").set("customer", "Acme Corp").update::<Invoice>().unwrap();
+    db.query::<Invoice>().where_eq("id", "42").set("customer", "Globex").update::<Invoice>().unwrap();
+
+    // Failed writes leave no history
+    invoice.total = -5;
+    assert!(db.update(&mut invoice).is_err());
+
+    db.set_actor(None);
+    db.delete::<Invoice>(1).unwrap();
+    db.force_delete::<Invoice>(1).unwrap();
+
+    let history = db.history::<Invoice>(1).unwrap();
+    let actions: Vec<Action> = history.iter().map(|entry| entry.action).collect();
+    assert_eq!(actions, [Action::Insert, Action::Update, Action::Update, Action::Delete, Action::Delete]);
+    assert!(history.iter().all(|entry| entry.row_id == 1 && !entry.changed_at.is_empty()));
+
+    assert_eq!(history[0].old_values, None);
+    assert_eq!(
+        history[0].new_values,
+        Some(json!({"id": 1, "customer": "Acme", "total": 100, "deleted_at": null}))
+    );
+    assert_eq!(history[0].actor, None);
+
+    assert_eq!(history[1].old_values.as_ref().unwrap()["total"], 100);
+    assert_eq!(history[1].new_values.as_ref().unwrap()["total"], 120);
+    assert_eq!(history[1].actor.as_deref(), Some("user:7"));
+    assert_eq!(history[2].new_values.as_ref().unwrap()["customer"], "Acme Corp");
+    assert_eq!(history[2].actor.as_deref(), Some("user:7"));
+
+    // Soft deletes keep the row with deleted_at set; permanent deletes have no new values
+    assert!(history[3].new_values.as_ref().unwrap()["deleted_at"].is_string());
+    assert_eq!(history[3].actor, None);
+    assert_eq!(history[4].new_values, None);
+    assert_eq!(history[4].old_values.as_ref().unwrap()["customer"], "Acme Corp");
+
+    assert_eq!(db.history::<Invoice>(42).unwrap().len(), 1);
+    assert!(matches!(db.history::<User>(1), Err(rusqlite::Error::InvalidQuery)));
+}
+
+#[test]
+fn test_audit_statements_are_logged() {
+    use crate::logging::QueryEvent;
+    use std::sync::{Arc, Mutex};
+
+    let logged = Arc::new(Mutex::new(Vec::<(String, Vec<String>)>::new()));
+    let sink = Arc::clone(&logged);
+    let db = Database::connect_in_memory()
+        .unwrap()
+        .with_redacted_fields(&["customer"])
+        .with_query_logger(move |event: &QueryEvent<'_>| {
+            sink.lock().unwrap().push((event.sql.to_string(), event.params.to_vec()))
+        });
+    db.create_table::<Invoice>().unwrap();
+
+    let mut invoice = Invoice { id: 1, customer: "Initech".to_string(), total: 10, deleted_at: None };
+    db.insert(&mut invoice).unwrap();
+    invoice.total = 20;
+    db.update(&mut invoice).unwrap();
+    db.history::<Invoice>(1).unwrap();
+
+    let logged = logged.lock().unwrap();
+    let sql: Vec<&str> = logged.iter().map(|(sql, _)| sql.as_str()).collect();
+    assert!(sql.contains(&"SAVEPOINT pebble_audit") && sql.contains(&"RELEASE pebble_audit"));
+    assert!(sql.iter().any(|sql| sql.starts_with(r#"SELECT "id", json_object("#)));
+    assert!(sql.iter().any(|sql| sql.ends_with(r#"FROM "invoice_history" WHERE "row_id" = ? ORDER BY "id""#)));
+
+    // Snapshots mask redacted fields inside their JSON
+    let entries: Vec<&Vec<String>> = logged
+        .iter()
+        .filter(|(sql, _)| sql.starts_with(r#"INSERT INTO "invoice_history""#))
+        .map(|(_, params)| params)
+        .collect();
+    assert_eq!(entries.len(), 2);
+    assert!(entries[1][2].contains(r#""customer":"[REDACTED]""#) && entries[1][2].contains(r#""total":10"#));
+    assert!(!logged.iter().flat_map(|(_, params)| params).any(|param| param.contains("Initech")));
+    drop(logged);
+
+    // Audit statements are prepared through the statement cache
+    let misses = db.statement_cache_stats().misses;
+    invoice.total = 30;
+    db.update(&mut invoice).unwrap();
+    assert_eq!(db.statement_cache_stats().misses, misses);
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Product {
+    id: i32,
+    name: String,
+    description: String,
+    category: String,
+    deleted_at: Option<String>,
+}
+
+impl Model for Product {
+    fn table_name() -> &'static str {
+        "products"
+    }
+
+    fn fields() -> &'static [&'static str] {
+        &["id", "name", "description", "category", "deleted_at"]
+    }
+
+    fn deleted_at_field() -> Option<&'static str> {
+        Some("deleted_at")
+    }
+
+    fn search_fields() -> &'static [&'static str] {
+        &["name", "description"]
+    }
+}
+
+#[test]
+fn test_full_text_search() {
+    let db = Database::connect_in_memory().unwrap();
+    db.create_table::<Product>().unwrap();
+
+    let products = [
+        ("Wireless Mouse", "A wireless mouse with a wireless USB receiver", "Accessories"),
+        ("Mechanical Keyboard", "Clicky keyboard with an optional wireless mode and many extra keys for gaming", "Accessories"),
+        ("USB Cable", "Braided cable", "Cables"),
+    ];
+    for (i, (name, description, category)) in products.iter().enumerate() {
+        db.insert(&mut Product {
+            id: i as i32 + 1,
+            name: name.to_string(),
+            description: description.to_string(),
+            category: category.to_string(),
+            deleted_at: None,
+        })
+        .unwrap();
+    }
+
+    // Best matches come first
+    let found = db.query::<Product>().search("wireless").fetch::<Product>().unwrap();
+    assert_eq!(found.iter().map(|p| p.id).collect::<Vec<_>>(), [1, 2]);
+
+    let hits = db
+        .query::<Product>()
+        .search("usb")
+        .highlight("name", "[", "]")
+        .snippet("description", "<b>", "</b>", "...", 3)
+        .fetch_hits::<Product>()
+        .unwrap();
+    assert_eq!(hits.len(), 2);
+    assert!(hits[0].rank <= hits[1].rank);
+    let usb_cable = hits.iter().find(|hit| hit.model.id == 3).unwrap();
+    assert_eq!(usb_cable.fragments, ["[USB] Cable", "Braided cable"]);
+    let mouse = hits.iter().find(|hit| hit.model.id == 1).unwrap();
+    assert_eq!(mouse.fragments[0], "Wireless Mouse");
+    assert!(mouse.fragments[1].contains("<b>USB</b>"));
+
+    // Searches combine with filters, ordering and limits
+    let accessories = db
+        .query::<Product>()
+        .search("wireless OR cable")
+        .where_eq("category", "Accessories")
+        .order_by("name", true)
+        .limit(1)
+        .fetch::<Product>()
+        .unwrap();
+    assert_eq!(accessories[0].name, "Mechanical Keyboard");
+    let (sql, params) = db.query::<Product>().search("cable").to_sql().unwrap();
+    assert!(sql.contains(r#"WHERE "products_fts" MATCH ?"#));
+    assert_eq!(params.len(), 1);
+
+    // Triggers keep the index in sync with updates, deletes and raw writes
+    let mut cable = db.find_by_id::<Product>(3).unwrap().unwrap();
+    cable.name = "Lightning Cord".to_string();
+    db.update(&mut cable).unwrap();
+    assert_eq!(db.query::<Product>().search("usb").fetch::<Product>().unwrap().len(), 1);
+    assert_eq!(db.query::<Product>().search("lightning").fetch::<Product>().unwrap()[0].id, 3);
+
+    db.raw_execute(
+        "INSERT INTO products (id, name, description, category) VALUES (4, 'Wireless Headset', 'Headset', 'Audio')",
+        [],
+    )
+    .unwrap();
+    assert_eq!(db.query::<Product>().search("headset").fetch::<Product>().unwrap()[0].id, 4);
+
+    // Soft-deleted rows are hidden, permanently deleted rows leave the index
+    db.delete::<Product>(4).unwrap();
+    assert!(db.query::<Product>().search("headset").fetch::<Product>().unwrap().is_empty());
+    assert_eq!(db.query::<Product>().search("headset").with_trashed().fetch::<Product>().unwrap().len(), 1);
+    db.force_delete::<Product>(4).unwrap();
+    assert!(db.query::<Product>().search("headset").with_trashed().fetch::<Product>().unwrap().is_empty());
+
+    // Bulk updates can target search matches
+    let changed = db.query::<Product>().search("wireless").set("category", "Wireless").update::<Product>().unwrap();
+    assert_eq!(changed, 2);
+    assert_eq!(db.query::<Product>().where_eq("category", "Wireless").fetch::<Product>().unwrap().len(), 2);
+
+    // A missing index is rebuilt from existing rows
+    db.raw_execute("DROP TABLE products_fts", []).unwrap();
+    db.create_table::<Product>().unwrap();
+    assert_eq!(db.query::<Product>().search("keyboard").fetch::<Product>().unwrap()[0].id, 2);
+
+    assert!(matches!(
+        db.query::<Product>().search("usb").highlight("category", "[", "]").fetch_hits::<Product>(),
+        Err(rusqlite::Error::InvalidColumnName(_))
+    ));
+    assert!(matches!(db.query::<User>().search("alice").fetch::<User>(), Err(rusqlite::Error::InvalidQuery)));
+    assert!(matches!(db.query::<Product>().fetch_hits::<Product>(), Err(rusqlite::Error::InvalidQuery)));
+
+    db.drop_table::<Product>().unwrap();
+    assert!(db.raw_execute("DROP TABLE products_fts", []).is_err());
+}
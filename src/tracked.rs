@@ -0,0 +1,63 @@
+use crate::model::Model;
+use crate::ser::model_values;
+use rusqlite::types::Value as SqlValue;
+use rusqlite::Result as SqliteResult;
+use std::ops::{Deref, DerefMut};
+
+/// A model that remembers the values it was loaded with
+///
+/// Edit it through `Deref`/`DerefMut` and pass it to `Database::save_changes`,
+/// which updates only the columns whose values differ from the snapshot.
+pub struct Tracked<T> {
+    pub(crate) model: T,
+    pub(crate) original: Vec<SqlValue>,
+}
+
+impl<T: Model> Tracked<T> {
+    /// Start tracking changes from the model's current values
+    pub fn new(model: T) -> SqliteResult<Self> {
+        let original = model_values(&model)?;
+        Ok(Tracked { model, original })
+    }
+
+    /// Fields whose values differ from the snapshot, in `fields()` order
+    pub fn changed_fields(&self) -> SqliteResult<Vec<&'static str>> {
+        let current = model_values(&self.model)?;
+        Ok(T::fields()
+            .iter()
+            .zip(current.iter().zip(&self.original))
+            .filter(|(_, (now, then))| now != then)
+            .map(|(field, _)| *field)
+            .collect())
+    }
+
+    /// True when any field differs from the snapshot
+    pub fn is_changed(&self) -> SqliteResult<bool> {
+        Ok(!self.changed_fields()?.is_empty())
+    }
+
+    /// Stop tracking and return the model
+    pub fn into_inner(self) -> T {
+        self.model
+    }
+
+    /// Take the model's current values as the new snapshot
+    pub(crate) fn snapshot(&mut self) -> SqliteResult<()> {
+        self.original = model_values(&self.model)?;
+        Ok(())
+    }
+}
+
+impl<T> Deref for Tracked<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.model
+    }
+}
+
+impl<T> DerefMut for Tracked<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.model
+    }
+}
@@ -1,48 +1,193 @@
-use crate::model::Model;
+use crate::model::{ColumnType, Model};
 use rusqlite::{Result as SqliteResult, Row};
-use serde::de::{self, Deserialize, Deserializer, Visitor, MapAccess, SeqAccess, IntoDeserializer};
+use rusqlite::types::{Type, Value as SqlValue, ValueRef};
+use serde::de::{self, DeserializeOwned, Deserializer, Visitor, MapAccess, SeqAccess, IntoDeserializer};
 use serde_json::Value;
 use std::fmt::Display;
 
+/// How row values are matched against model field types
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DeserializeMode {
+    /// Coerce where possible: numeric strings parse into numbers, out-of-range
+    /// integers wrap and NaN becomes 0
+    #[default]
+    Loose,
+    /// Reject type mismatches, integer overflow, non-finite floats and integers
+    /// too large to load exactly into floating point fields
+    /// Integers stored as text are only accepted in canonical form ("42"),
+    /// which is how TEXT columns hold them
+    Strict,
+}
+
+/// Quote a table or column name so keywords like `order` and special characters are safe in SQL
+pub fn quote_ident(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// Quote each name and join them into a column list
+pub fn quote_idents(names: &[&str]) -> String {
+    names.iter().map(|name| quote_ident(name)).collect::<Vec<_>>().join(", ")
+}
+
+/// Reject column names the model does not declare in `fields()`
+pub fn check_field<T: Model>(field: &str) -> SqliteResult<()> {
+    if T::fields().contains(&field) {
+        Ok(())
+    } else {
+        Err(rusqlite::Error::InvalidColumnName(field.to_string()))
+    }
+}
+
 /// Helper function to convert a Row to a Model instance
-/// Uses a custom deserializer to handle type mismatches (e.g. TEXT -> Integer)
-pub fn row_to_model<T: Model>(row: &Row, fields: &[&str]) -> SqliteResult<T> {
-    let mut json_map = serde_json::Map::new();
-
-    for (idx, field) in fields.iter().enumerate() {
-        // Try to get the value as different types
-        let value: serde_json::Value = if let Ok(v) = row.get::<_, i64>(idx) {
-            serde_json::Value::Number(v.into())
-        } else if let Ok(v) = row.get::<_, String>(idx) {
-            serde_json::Value::String(v)
-        } else if let Ok(v) = row.get::<_, f64>(idx) {
-            serde_json::Value::Number(
-                serde_json::Number::from_f64(v).unwrap_or_else(|| 0.into())
-            )
-        } else {
-            serde_json::Value::Null
-        };
-
-        json_map.insert(field.to_string(), value);
-    }
-
-    let value = Value::Object(json_map);
-
-    // Use custom deserializer
-    let loose_value = LooseValue(value);
-    T::deserialize(loose_value).map_err(|e| rusqlite::Error::FromSqlConversionFailure(
-        0,
-        rusqlite::types::Type::Text,
-        Box::new(e)
-    ))
+/// Columns are found by name, so they may be selected in any order and columns
+/// the model does not declare are ignored; each is decoded by its declared column type
+/// and the model's `after_load` hook runs on the result
+/// Fields whose column was not selected are left to serde: `Option` and `#[serde(default)]`
+/// fields take their defaults, any other field fails with `InvalidColumnName`
+pub fn row_to_model<T: Model>(row: &Row, mode: DeserializeMode) -> SqliteResult<T> {
+    let names = row.as_ref().column_names();
+    let columns: Vec<(usize, &str)> = T::fields()
+        .iter()
+        .filter_map(|field| Some((names.iter().position(|name| name == field)?, *field)))
+        .collect();
+    let mut model: T = decode_row(row, &columns, T::column_type, Some(T::primary_key()), mode)?;
+    model.after_load();
+    Ok(model)
+}
+
+/// Convert a row from arbitrary SQL into any deserializable struct, matching
+/// columns to fields by the names the statement gives them
+/// `column_type` decodes enum and decimal columns; `key` names the column used
+/// to identify the row in error messages
+pub fn row_to_struct<T: DeserializeOwned>(
+    row: &Row,
+    column_type: fn(&str) -> ColumnType,
+    key: Option<&str>,
+    mode: DeserializeMode,
+) -> SqliteResult<T> {
+    let names = row.as_ref().column_names();
+    let columns: Vec<(usize, &str)> = names.into_iter().enumerate().collect();
+    decode_row(row, &columns, column_type, key, mode)
+}
+
+/// Decode the given `(index, name)` columns of the row
+fn decode_row<T: DeserializeOwned>(
+    row: &Row,
+    columns: &[(usize, &str)],
+    column_type: fn(&str) -> ColumnType,
+    key: Option<&str>,
+    mode: DeserializeMode,
+) -> SqliteResult<T> {
+    let index_of = |column: &str| columns.iter().find(|(_, name)| *name == column).map(|(idx, _)| *idx);
+
+    // Identify the row in error messages by its primary key when it was selected
+    let row_label = key
+        .and_then(|key| Some((key, row.get_ref(index_of(key)?).ok()?)))
+        .map(|(key, value)| match value {
+            ValueRef::Integer(i) => format!("with {} = {}", key, i),
+            ValueRef::Text(t) => format!("with {} = {:?}", key, String::from_utf8_lossy(t)),
+            _ => format!("with {} = NULL", key),
+        });
+    let conversion_error = |idx: usize, err: DeserError| {
+        let err = DeserError { row: row_label.clone(), ..err };
+        let data_type = row.get_ref(idx).map(|v| v.data_type()).unwrap_or(Type::Null);
+        rusqlite::Error::FromSqlConversionFailure(idx, data_type, Box::new(err))
+    };
+
+    let mut values = Vec::with_capacity(columns.len());
+    for &(idx, name) in columns {
+        let value = column_value(row.get_ref(idx)?, column_type(name), mode)
+            .map_err(|e| conversion_error(idx, DeserError { column: Some(name.to_string()), ..e }))?;
+        values.push((name.to_string(), value));
+    }
+
+    T::deserialize(RowDeserializer { columns: values, mode }).map_err(|e| match (&e.column, e.missing) {
+        // A required field of the row itself, rather than inside a column's value, was not selected
+        (None, Some(field)) => rusqlite::Error::InvalidColumnName(field.to_string()),
+        _ => {
+            let idx = e.column.as_deref().and_then(index_of).unwrap_or(0);
+            conversion_error(idx, e)
+        }
+    })
+}
+
+/// Read a column value, mapping enum columns back to variant names and
+/// scaled decimals back to decimal text
+fn column_value(value: ValueRef, column_type: ColumnType, mode: DeserializeMode) -> Result<SqlValue, DeserError> {
+    let value = match value {
+        ValueRef::Real(f) if !f.is_finite() => {
+            if mode == DeserializeMode::Strict {
+                return Err(de::Error::custom(format!("non-finite float {}", f)));
+            }
+            SqlValue::Real(0.0)
+        }
+        ValueRef::Text(t) => SqlValue::Text(
+            String::from_utf8(t.to_vec()).map_err(|e| de::Error::custom(format!("invalid UTF-8 text: {}", e)))?,
+        ),
+        value => value.into(),
+    };
+
+    match (column_type, &value) {
+        (_, SqlValue::Null) => Ok(value),
+        (ColumnType::TextEnum(names), SqlValue::Text(s)) if names.contains(&s.as_str()) => Ok(value),
+        (ColumnType::IntegerEnum(variants), SqlValue::Integer(i)) => variants
+            .iter()
+            .find(|(_, discriminant)| discriminant == i)
+            .map(|(name, _)| SqlValue::Text(name.to_string()))
+            .ok_or_else(|| de::Error::custom(format!("unknown enum value {}", i))),
+        (ColumnType::TextEnum(_) | ColumnType::IntegerEnum(_), SqlValue::Text(s)) => {
+            Err(de::Error::custom(format!("unknown enum value {:?}", s)))
+        }
+        (ColumnType::TextEnum(_) | ColumnType::IntegerEnum(_), _) => {
+            Err(de::Error::custom(format!("unknown enum value of type {}", value.data_type())))
+        }
+        (ColumnType::Decimal(scale), SqlValue::Integer(i)) => Ok(SqlValue::Text(crate::decimal::from_scaled(*i, scale))),
+        _ => Ok(value),
+    }
 }
 
+/// Convert a JSON value to a SQLite value for binding query parameters
+/// NULL stays NULL, nested arrays and objects are stored as JSON text
+pub fn to_sql_value(value: &Value) -> SqliteResult<SqlValue> {
+    Ok(match value {
+        Value::Null => SqlValue::Null,
+        Value::Bool(b) => SqlValue::Text(b.to_string()),
+        Value::Number(n) => {
+            if let Some(i) = n.as_i64() {
+                SqlValue::Integer(i)
+            } else if n.is_f64() {
+                SqlValue::Real(n.as_f64().unwrap_or_default())
+            } else {
+                SqlValue::Text(n.to_string())
+            }
+        }
+        Value::String(s) => SqlValue::Text(s.clone()),
+        _ => SqlValue::Text(serde_json::to_string(value)
+            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?),
+    })
+}
+
+/// Error raised while mapping a row onto a model
+/// Carries the offending column and row when known
 #[derive(Debug)]
-pub struct DeserError(String);
+pub struct DeserError {
+    message: String,
+    column: Option<String>,
+    row: Option<String>,
+    /// Field serde found no value for
+    missing: Option<&'static str>,
+}
 
 impl Display for DeserError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.0)
+        write!(f, "{}", self.message)?;
+        if let Some(column) = &self.column {
+            write!(f, " in column `{}`", column)?;
+        }
+        if let Some(row) = &self.row {
+            write!(f, " of row {}", row)?;
+        }
+        Ok(())
     }
 }
 
@@ -50,26 +195,71 @@ impl std::error::Error for DeserError {}
 
 impl de::Error for DeserError {
     fn custom<T: Display>(msg: T) -> Self {
-        DeserError(msg.to_string())
+        DeserError {
+            message: msg.to_string(),
+            column: None,
+            row: None,
+            missing: None,
+        }
+    }
+
+    fn missing_field(field: &'static str) -> Self {
+        DeserError {
+            missing: Some(field),
+            ..de::Error::custom(format_args!("missing field `{}`", field))
+        }
     }
 }
 
-pub struct LooseValue(pub Value);
+/// Deserializer presenting a row's columns as a map keyed by field name
+struct RowDeserializer {
+    columns: Vec<(String, SqlValue)>,
+    mode: DeserializeMode,
+}
+
+impl<'de> Deserializer<'de> for RowDeserializer {
+    type Error = DeserError;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where V: Visitor<'de> {
+        visitor.visit_map(LooseMapAccess { iter: self.columns.into_iter(), value: None, key: None, mode: self.mode })
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+/// Deserializer for a single column value
+/// Coerces between SQLite storage classes and field types according to the mode
+pub struct LooseValue(pub SqlValue, pub DeserializeMode);
 
 macro_rules! impl_int_deser {
-    ($name:ident, $visit:ident, $type:ty, $as_method:ident) => {
+    ($name:ident, $visit:ident, $type:ty) => {
         fn $name<V>(self, visitor: V) -> Result<V::Value, Self::Error>
         where V: Visitor<'de> {
+            let strict = self.1 == DeserializeMode::Strict;
             match self.0 {
-                Value::String(ref s) => {
+                SqlValue::Text(ref s) => {
                     if let Ok(n) = s.parse::<$type>() {
-                        return visitor.$visit(n);
+                        if !strict || n.to_string() == *s {
+                            return visitor.$visit(n);
+                        }
                     }
                 }
-                Value::Number(ref n) => {
-                     if let Some(i) = n.$as_method() {
-                         return visitor.$visit(i as $type);
-                     }
+                // Negative values never wrap into unsigned types
+                SqlValue::Integer(i) if <$type>::MIN != 0 || i >= 0 => {
+                    if !strict {
+                        return visitor.$visit(i as $type);
+                    }
+                    return match <$type>::try_from(i) {
+                        Ok(v) => visitor.$visit(v),
+                        Err(_) => Err(de::Error::custom(format!(
+                            "integer {} out of range for {}", i, stringify!($type)
+                        ))),
+                    };
                 }
                 _ => {}
             }
@@ -79,90 +269,162 @@ macro_rules! impl_int_deser {
 }
 
 macro_rules! impl_float_deser {
-    ($name:ident, $visit:ident, $type:ty, $as_method:ident) => {
+    ($name:ident, $visit:ident, $type:ty) => {
         fn $name<V>(self, visitor: V) -> Result<V::Value, Self::Error>
         where V: Visitor<'de> {
-            match self.0 {
-                Value::String(ref s) => {
-                    if let Ok(n) = s.parse::<$type>() {
-                        return visitor.$visit(n);
-                    }
-                }
-                Value::Number(ref n) => {
-                     if let Some(i) = n.$as_method() {
-                         return visitor.$visit(i as $type);
-                     }
+            let strict = self.1 == DeserializeMode::Strict;
+            let parsed = match self.0 {
+                SqlValue::Integer(i) if strict && (i as $type) as i64 != i => {
+                    return Err(de::Error::custom(format!(
+                        "integer {} cannot be represented exactly as {}", i, stringify!($type)
+                    )));
                 }
-                _ => {}
+                SqlValue::Text(ref s) => s.parse::<$type>().ok(),
+                SqlValue::Real(f) => Some(f as $type),
+                SqlValue::Integer(i) => Some(i as $type),
+                _ => None,
+            };
+            match parsed {
+                Some(n) if strict && !n.is_finite() => Err(de::Error::custom(format!(
+                    "{:?} out of range for {}", self.0, stringify!($type)
+                ))),
+                Some(n) => visitor.$visit(n),
+                None => self.deserialize_any(visitor),
             }
-            self.deserialize_any(visitor)
         }
     }
 }
 
+impl LooseValue {
+    /// Nested values are stored as JSON text; parse it and hand over to serde_json
+    fn deserialize_json<'de, V>(self, visitor: V) -> Result<V::Value, DeserError>
+    where V: Visitor<'de> {
+        if let SqlValue::Text(ref s) = self.0 {
+            if let Ok(json) = serde_json::from_str::<Value>(s) {
+                return json.deserialize_any(visitor).map_err(de::Error::custom);
+            }
+        }
+        self.deserialize_any(visitor)
+    }
+}
+
 impl<'de> Deserializer<'de> for LooseValue {
     type Error = DeserError;
 
     fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where V: Visitor<'de> {
         match self.0 {
-            Value::Null => visitor.visit_unit(),
-            Value::Bool(b) => visitor.visit_bool(b),
-            Value::Number(n) => {
-                if let Some(i) = n.as_i64() {
-                    visitor.visit_i64(i)
-                } else if let Some(u) = n.as_u64() {
-                    visitor.visit_u64(u)
-                } else if let Some(f) = n.as_f64() {
-                    visitor.visit_f64(f)
-                } else {
-                    Err(de::Error::custom("invalid number"))
-                }
-            },
-            Value::String(s) => visitor.visit_string(s),
-            Value::Array(a) => visitor.visit_seq(LooseSeqAccess { iter: a.into_iter() }),
-            Value::Object(o) => visitor.visit_map(LooseMapAccess { iter: o.into_iter(), value: None }),
+            SqlValue::Null => visitor.visit_unit(),
+            SqlValue::Integer(i) => visitor.visit_i64(i),
+            SqlValue::Real(f) => visitor.visit_f64(f),
+            SqlValue::Text(s) => visitor.visit_string(s),
+            SqlValue::Blob(b) => visitor.visit_byte_buf(b),
         }
     }
 
-    impl_int_deser!(deserialize_i8, visit_i8, i8, as_i64);
-    impl_int_deser!(deserialize_i16, visit_i16, i16, as_i64);
-    impl_int_deser!(deserialize_i32, visit_i32, i32, as_i64);
-    impl_int_deser!(deserialize_i64, visit_i64, i64, as_i64);
+    impl_int_deser!(deserialize_i8, visit_i8, i8);
+    impl_int_deser!(deserialize_i16, visit_i16, i16);
+    impl_int_deser!(deserialize_i32, visit_i32, i32);
+    impl_int_deser!(deserialize_i64, visit_i64, i64);
+    impl_int_deser!(deserialize_i128, visit_i128, i128);
+
+    impl_int_deser!(deserialize_u8, visit_u8, u8);
+    impl_int_deser!(deserialize_u16, visit_u16, u16);
+    impl_int_deser!(deserialize_u32, visit_u32, u32);
+    impl_int_deser!(deserialize_u64, visit_u64, u64);
+    impl_int_deser!(deserialize_u128, visit_u128, u128);
+
+    impl_float_deser!(deserialize_f32, visit_f32, f32);
+    impl_float_deser!(deserialize_f64, visit_f64, f64);
 
-    impl_int_deser!(deserialize_u8, visit_u8, u8, as_u64);
-    impl_int_deser!(deserialize_u16, visit_u16, u16, as_u64);
-    impl_int_deser!(deserialize_u32, visit_u32, u32, as_u64);
-    impl_int_deser!(deserialize_u64, visit_u64, u64, as_u64);
+    fn deserialize_bool<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where V: Visitor<'de> {
+        // Booleans are stored as 0/1; TEXT columns hold them as "true"/"false"
+        let strict = self.1 == DeserializeMode::Strict;
+        match self.0 {
+            SqlValue::Integer(0) => visitor.visit_bool(false),
+            SqlValue::Integer(1) => visitor.visit_bool(true),
+            SqlValue::Integer(i) if !strict => visitor.visit_bool(i != 0),
+            SqlValue::Text(ref s) => match s.as_str() {
+                "0" | "false" => visitor.visit_bool(false),
+                "1" | "true" => visitor.visit_bool(true),
+                _ => self.deserialize_any(visitor),
+            },
+            _ => self.deserialize_any(visitor),
+        }
+    }
 
-    impl_float_deser!(deserialize_f32, visit_f32, f32, as_f64);
-    impl_float_deser!(deserialize_f64, visit_f64, f64, as_f64);
+    fn deserialize_bytes<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where V: Visitor<'de> {
+        self.deserialize_any(visitor)
+    }
+
+    fn deserialize_byte_buf<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where V: Visitor<'de> {
+        self.deserialize_any(visitor)
+    }
 
     fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where V: Visitor<'de> {
         match self.0 {
-            Value::Null => visitor.visit_none(),
+            SqlValue::Null => visitor.visit_none(),
             _ => visitor.visit_some(self),
         }
     }
 
-    fn deserialize_enum<V>(self, _name: &str, _variants: &'static [&'static str], visitor: V) -> Result<V::Value, Self::Error>
+    fn deserialize_newtype_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value, Self::Error>
+    where V: Visitor<'de> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where V: Visitor<'de> {
+        match self.0 {
+            SqlValue::Blob(b) => visitor.visit_seq(LooseSeqAccess { iter: b.into_iter() }),
+            _ => self.deserialize_json(visitor),
+        }
+    }
+
+    fn deserialize_tuple<V>(self, _len: usize, visitor: V) -> Result<V::Value, Self::Error>
+    where V: Visitor<'de> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V>(self, _name: &'static str, _len: usize, visitor: V) -> Result<V::Value, Self::Error>
     where V: Visitor<'de> {
-         if let Value::String(s) = self.0 {
-             visitor.visit_enum(s.into_deserializer())
-         } else {
-             self.deserialize_any(visitor)
-         }
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_map<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where V: Visitor<'de> {
+        self.deserialize_json(visitor)
+    }
+
+    fn deserialize_struct<V>(self, _name: &'static str, _fields: &'static [&'static str], visitor: V) -> Result<V::Value, Self::Error>
+    where V: Visitor<'de> {
+        self.deserialize_json(visitor)
+    }
+
+    fn deserialize_enum<V>(self, name: &'static str, variants: &'static [&'static str], visitor: V) -> Result<V::Value, Self::Error>
+    where V: Visitor<'de> {
+        match self.0 {
+            // Data-carrying variants are stored as JSON objects
+            SqlValue::Text(ref s) if s.starts_with('{') => match serde_json::from_str::<Value>(s) {
+                Ok(json) => json.deserialize_enum(name, variants, visitor).map_err(de::Error::custom),
+                Err(_) => visitor.visit_enum(s.clone().into_deserializer()),
+            },
+            SqlValue::Text(s) => visitor.visit_enum(s.into_deserializer()),
+            _ => self.deserialize_any(visitor),
+        }
     }
 
     serde::forward_to_deserialize_any! {
-        bool char str string bytes byte_buf unit unit_struct newtype_struct seq tuple
-        tuple_struct map struct identifier ignored_any
+        char str string unit unit_struct identifier ignored_any
     }
 }
 
 struct LooseSeqAccess {
-    iter: std::vec::IntoIter<Value>,
+    iter: std::vec::IntoIter<u8>,
 }
 
 impl<'de> SeqAccess<'de> for LooseSeqAccess {
@@ -171,15 +433,17 @@ impl<'de> SeqAccess<'de> for LooseSeqAccess {
     fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, Self::Error>
     where T: de::DeserializeSeed<'de> {
         match self.iter.next() {
-            Some(value) => seed.deserialize(LooseValue(value)).map(Some),
+            Some(byte) => seed.deserialize(byte.into_deserializer()).map(Some),
             None => Ok(None),
         }
     }
 }
 
 struct LooseMapAccess {
-    iter: serde_json::map::IntoIter,
-    value: Option<Value>,
+    iter: std::vec::IntoIter<(String, SqlValue)>,
+    value: Option<SqlValue>,
+    key: Option<String>,
+    mode: DeserializeMode,
 }
 
 impl<'de> MapAccess<'de> for LooseMapAccess {
@@ -190,6 +454,7 @@ impl<'de> MapAccess<'de> for LooseMapAccess {
         match self.iter.next() {
             Some((key, value)) => {
                 self.value = Some(value);
+                self.key = Some(key.clone());
                 seed.deserialize(key.into_deserializer()).map(Some)
             }
             None => Ok(None),
@@ -199,7 +464,11 @@ impl<'de> MapAccess<'de> for LooseMapAccess {
     fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Self::Error>
     where V: de::DeserializeSeed<'de> {
         match self.value.take() {
-            Some(value) => seed.deserialize(LooseValue(value)),
+            // Remember which key failed so errors can name the column
+            Some(value) => seed.deserialize(LooseValue(value, self.mode)).map_err(|e| DeserError {
+                column: e.column.or_else(|| self.key.take()),
+                ..e
+            }),
             None => Err(de::Error::custom("value is missing")),
         }
     }
@@ -0,0 +1,234 @@
+//! Model validation
+//!
+//! Models describe their rules in `Model::validate`, which `insert` and `update`
+//! run after the `before_*` hooks and before anything is written. Rules look at
+//! field values as they will be stored, so they are named by field:
+//!
+//! ```rust
+//! use pebble::{Database, Model};
+//! use pebble::validate::{ValidationErrors, Validator};
+//! use serde::{Deserialize, Serialize};
+//!
+//! #[derive(Serialize, Deserialize)]
+//! struct Signup {
+//!     id: i32,
+//!     name: String,
+//!     age: i32,
+//! }
+//!
+//! impl Model for Signup {
+//!     fn table_name() -> &'static str {
+//!         "signups"
+//!     }
+//!
+//!     fn fields() -> &'static [&'static str] {
+//!         &["id", "name", "age"]
+//!     }
+//!
+//!     fn validate(&self, v: &mut Validator<'_>) {
+//!         v.required("name");
+//!         v.length("name", 2..=40);
+//!         v.range("age", 13, 130);
+//!     }
+//! }
+//!
+//! let db = Database::connect_in_memory().unwrap();
+//! db.create_table::<Signup>().unwrap();
+//!
+//! let err = db.insert(&mut Signup { id: 1, name: String::new(), age: 7 }).unwrap_err();
+//! let errors = ValidationErrors::from_error(&err).unwrap();
+//! assert_eq!(errors.fields(), vec!["name", "name", "age"]);
+//! ```
+//!
+//! With the `regex` feature, `Validator::regex` checks text fields against a pattern.
+
+use crate::model::ColumnType;
+use rusqlite::types::Value as SqlValue;
+use std::fmt;
+use std::ops::{Bound, RangeBounds};
+
+/// Collects validation failures for one model
+pub struct Validator<'v> {
+    fields: &'v [&'static str],
+    values: &'v [SqlValue],
+    column_type: fn(&str) -> ColumnType,
+    errors: Vec<FieldError>,
+}
+
+/// One failed rule
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+/// Every rule a model failed, in the order they were checked
+///
+/// `insert` and `update` return this boxed in `rusqlite::Error::ToSqlConversionFailure`;
+/// use `ValidationErrors::from_error` to get it back.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationErrors {
+    pub errors: Vec<FieldError>,
+}
+
+impl<'v> Validator<'v> {
+    pub(crate) fn new(fields: &'v [&'static str], values: &'v [SqlValue], column_type: fn(&str) -> ColumnType) -> Self {
+        Validator { fields, values, column_type, errors: Vec::new() }
+    }
+
+    /// The field must be set: not NULL and, for text, not blank
+    pub fn required(&mut self, field: &str) -> &mut Self {
+        let missing = match self.value(field) {
+            Some(SqlValue::Null) => true,
+            Some(SqlValue::Text(s)) => s.trim().is_empty(),
+            _ => false,
+        };
+        if missing {
+            self.add_error(field, "is required");
+        }
+        self
+    }
+
+    /// Text must have a number of characters (or a BLOB a number of bytes) within `range`
+    /// NULL passes; combine with `required` to reject it
+    pub fn length(&mut self, field: &str, range: impl RangeBounds<usize>) -> &mut Self {
+        let len = match self.value(field) {
+            Some(SqlValue::Text(s)) => s.chars().count(),
+            Some(SqlValue::Blob(b)) => b.len(),
+            _ => return self,
+        };
+        if !range.contains(&len) {
+            self.add_error(field, &format!("length must be {}", describe(&range)));
+        }
+        self
+    }
+
+    /// A number must lie between `min` and `max`, inclusive
+    /// Decimal columns are compared by value, not by their scaled storage; NULL passes
+    pub fn range(&mut self, field: &str, min: impl Into<f64>, max: impl Into<f64>) -> &mut Self {
+        let (min, max) = (min.into(), max.into());
+        let number = match (self.value(field), (self.column_type)(field)) {
+            (Some(SqlValue::Integer(i)), ColumnType::Decimal(scale)) => *i as f64 / 10f64.powi(scale as i32),
+            (Some(SqlValue::Integer(i)), _) => *i as f64,
+            (Some(SqlValue::Real(f)), _) => *f,
+            (Some(SqlValue::Text(s)), _) => match s.parse::<f64>() {
+                Ok(n) => n,
+                Err(_) => {
+                    self.add_error(field, "must be a number");
+                    return self;
+                }
+            },
+            _ => return self,
+        };
+        if !(min..=max).contains(&number) {
+            self.add_error(field, &format!("must be between {} and {}", min, max));
+        }
+        self
+    }
+
+    /// Text must match `pattern`; NULL passes
+    #[cfg(feature = "regex")]
+    pub fn regex(&mut self, field: &str, pattern: &regex::Regex) -> &mut Self {
+        if let Some(SqlValue::Text(s)) = self.value(field) {
+            if !pattern.is_match(s) {
+                self.add_error(field, &format!("must match {}", pattern.as_str()));
+            }
+        }
+        self
+    }
+
+    /// Run a custom check on the field's stored value, recording the returned message on failure
+    pub fn custom<F>(&mut self, field: &str, check: F) -> &mut Self
+    where F: FnOnce(&SqlValue) -> Result<(), String> {
+        if let Some(value) = self.value(field) {
+            if let Err(message) = check(value) {
+                self.add_error(field, &message);
+            }
+        }
+        self
+    }
+
+    /// Record a failure directly, e.g. for rules spanning several fields
+    pub fn add_error(&mut self, field: &str, message: &str) -> &mut Self {
+        self.errors.push(FieldError { field: field.to_string(), message: message.to_string() });
+        self
+    }
+
+    /// The failures recorded so far, or `Ok` when there are none
+    pub(crate) fn finish(self) -> rusqlite::Result<()> {
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(rusqlite::Error::ToSqlConversionFailure(Box::new(ValidationErrors { errors: self.errors })))
+        }
+    }
+
+    /// The field's stored value; rules naming unknown fields fail instead of passing silently
+    fn value(&mut self, field: &str) -> Option<&'v SqlValue> {
+        let values = self.values;
+        match self.fields.iter().position(|f| *f == field) {
+            Some(idx) => values.get(idx),
+            None => {
+                self.add_error(field, "is not a field of this model");
+                None
+            }
+        }
+    }
+}
+
+impl ValidationErrors {
+    /// Get the validation failures out of an error returned by `insert` or `update`
+    pub fn from_error(err: &rusqlite::Error) -> Option<&ValidationErrors> {
+        match err {
+            rusqlite::Error::ToSqlConversionFailure(e) => e.downcast_ref(),
+            _ => None,
+        }
+    }
+
+    /// Names of the failing fields, one per failure
+    pub fn fields(&self) -> Vec<&str> {
+        self.errors.iter().map(|e| e.field.as_str()).collect()
+    }
+}
+
+impl fmt::Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.field, self.message)
+    }
+}
+
+impl fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "validation failed: ")?;
+        for (i, error) in self.errors.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", error)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ValidationErrors {}
+
+/// Describe a length range, e.g. "between 2 and 40" or "at least 1"
+fn describe(range: &impl RangeBounds<usize>) -> String {
+    let min = match range.start_bound() {
+        Bound::Included(&n) => Some(n),
+        Bound::Excluded(&n) => Some(n + 1),
+        Bound::Unbounded => None,
+    };
+    let max = match range.end_bound() {
+        Bound::Included(&n) => Some(n),
+        Bound::Excluded(&n) => Some(n.saturating_sub(1)),
+        Bound::Unbounded => None,
+    };
+    match (min, max) {
+        (Some(min), Some(max)) if min == max => format!("exactly {}", min),
+        (Some(min), Some(max)) => format!("between {} and {}", min, max),
+        (Some(min), None) => format!("at least {}", min),
+        (None, Some(max)) => format!("at most {}", max),
+        (None, None) => "any".to_string(),
+    }
+}